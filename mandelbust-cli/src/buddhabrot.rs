@@ -0,0 +1,130 @@
+use crate::field::Field;
+use crate::mandelbrot::{Complex, Exponent, Fractal};
+use crate::opts::Interval;
+use crate::view::PixelMap;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
+
+/// samples are split into batches of this many, each with its own rng from `batch_rng`,
+/// so the result doesn't depend on how rayon schedules them
+const BATCH_SIZE: usize = 1 << 14;
+
+/// every point of the mandelbrot set lies in the disc of radius 2 inside this square, so `c` is
+/// only sampled from that
+const SAMPLE_RANGE: Interval = Interval {
+    lower: -2.,
+    upper: 2.,
+};
+
+/// the rng for one batch of samples. the seed and batch number both go into the key, so every
+/// pair gets its own stream, rather than neighbouring seeds sharing all but one of their batches
+fn batch_rng(seed: u64, batch: u64) -> StdRng {
+    let mut key = <StdRng as SeedableRng>::Seed::default();
+    key[..8].copy_from_slice(&seed.to_le_bytes());
+    key[8..16].copy_from_slice(&batch.to_le_bytes());
+    StdRng::from_seed(key)
+}
+
+/// how many times each pixel of the window is visited by orbits which escape, for buddhabrot
+/// and nebulabrot renders. `c` values are sampled uniformly at random, and the orbit of each
+/// one which escapes is traced over the window, which is `pixel_map` around `centre`. each of
+/// `channel_iters` is a separate iteration limit with its own density buffer, counting the
+/// orbits which escape within it, so passing one limit gives a buddhabrot and three give the
+/// red, green and blue channels of a nebulabrot.
+///
+/// the result has a field for each channel, laid out like `generate_escape_counts`, and is the
+/// same for the same `seed` however many threads it's spread over.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_densities(
+    fractal: Fractal,
+    exponent: Exponent,
+    centre: Complex,
+    pixel_map: &PixelMap,
+    width: usize,
+    height: usize,
+    channel_iters: &[usize],
+    bailout: f64,
+    samples: usize,
+    seed: u64,
+) -> Vec<Field<u32>> {
+    let max_iters = channel_iters.iter().copied().max().unwrap_or(0);
+    let bailout_sq = bailout.powf(2.);
+    let buffer_len = channel_iters.len() * width * height;
+    // the pixel an iterate lands on, if it's in the window
+    let pixel = |z: Complex| {
+        let (x, y) = pixel_map.pixel(z - centre);
+        ((0. ..width as f64).contains(&x) && (0. ..height as f64).contains(&y))
+            .then(|| y as usize * width + x as usize)
+    };
+
+    let counts = (0..samples.div_ceil(BATCH_SIZE))
+        .into_par_iter()
+        .fold(
+            || vec![0u32; buffer_len],
+            |mut counts, batch| {
+                let mut rng = batch_rng(seed, batch as u64);
+                let batch_samples = BATCH_SIZE.min(samples - batch * BATCH_SIZE);
+                for _ in 0..batch_samples {
+                    let c = loop {
+                        let c = Complex::new(
+                            SAMPLE_RANGE.lerp(rng.gen()),
+                            SAMPLE_RANGE.lerp(rng.gen()),
+                        );
+                        if c.abs_value_sq() <= 4. {
+                            break c;
+                        }
+                    };
+                    // the main cardioid and bulb never escape, so save iterating them
+                    if fractal == Fractal::Mandelbrot
+                        && exponent == Exponent::Integer(2)
+                        && c.in_main_bulbs()
+                    {
+                        continue;
+                    }
+                    let mut z = Complex::id();
+                    let Some(iters) = (1..=max_iters).find(|_| {
+                        z = fractal.iterate(z, &c, exponent);
+                        z.abs_value_sq() > bailout_sq
+                    }) else {
+                        continue;
+                    };
+
+                    // retrace the orbit now it's known to escape, up to its last point inside.
+                    // the first iterate is always c, which would only draw the sample disc
+                    let mut z = c;
+                    for _ in 2..iters {
+                        z = fractal.iterate(z, &c, exponent);
+                        let Some(i) = pixel(z) else {
+                            continue;
+                        };
+                        for (channel, &limit) in channel_iters.iter().enumerate() {
+                            if iters <= limit {
+                                counts[channel * width * height + i] += 1;
+                            }
+                        }
+                    }
+                }
+                counts
+            },
+        )
+        .reduce(
+            || vec![0u32; buffer_len],
+            |mut a, b| {
+                a.iter_mut().zip(b).for_each(|(a, b)| *a += b);
+                a
+            },
+        );
+
+    counts
+        .chunks(width * height)
+        .map(|channel| Field::new(width, height, channel.to_vec()))
+        .collect()
+}
+
+/// scales a channel's counts to between 0 and 1, taking the square root to bring out the
+/// faint orbits without blowing out the bright ones
+pub fn normalise_densities(counts: &Field<u32>) -> Field<f64> {
+    let max = counts.values().iter().copied().max().unwrap_or(0).max(1) as f64;
+    counts.map(|&count| (count as f64 / max).sqrt())
+}
@@ -1,6 +1,8 @@
-use crate::mandelbrot::Complex;
+use crate::coordinate::Point;
+use crate::mandelbrot::{Complex, Fractal};
 use crate::palette::ColorPalette;
 use crate::palette::ConfigRGB;
+use crate::zoom::Zoom;
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -13,8 +15,19 @@ pub struct Configuration {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NamedPoint {
-    pub point: Complex,
-    pub zoom: usize,
+    /// written as numbers in configs saved before centres could be more precise than an
+    /// `f64`, which still read as the same point
+    pub point: Point,
+    /// written as a whole number in configs saved before zooms could be fractional or deeper
+    /// than a `usize`, which still reads as the same zoom
+    pub zoom: Zoom,
+    /// missing from configs saved before fractals other than the mandelbrot set existed
+    #[serde(default)]
+    pub fractal: Fractal,
+    /// degrees the view is turned anticlockwise; missing from configs saved before views could
+    /// be turned
+    #[serde(default)]
+    pub rotation: f64,
 }
 
 impl Configuration {
@@ -192,8 +205,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: -0.0452407411,
                         im: 0.9868162204352258,
-                    },
-                    zoom: 51200000,
+                    }
+                    .into(),
+                    zoom: Zoom::new(51200000.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
             (
@@ -202,8 +218,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: 0.281717921930775,
                         im: 0.5771052841488505,
-                    },
-                    zoom: 102400000000,
+                    }
+                    .into(),
+                    zoom: Zoom::new(102400000000.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
             (
@@ -212,8 +231,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: 0.281717921930775,
                         im: 0.5771052841488505,
-                    },
-                    zoom: 25600000000,
+                    }
+                    .into(),
+                    zoom: Zoom::new(25600000000.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
             (
@@ -222,8 +244,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: 0.432539867562512,
                         im: 0.226118675951765,
-                    },
-                    zoom: 25000000000000,
+                    }
+                    .into(),
+                    zoom: Zoom::new(25000000000000.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
             (
@@ -232,8 +257,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: 0.2549870375144766,
                         im: -0.0005679790528465,
-                    },
-                    zoom: 200000,
+                    }
+                    .into(),
+                    zoom: Zoom::new(200000.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
             (
@@ -242,8 +270,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: -0.745428,
                         im: 0.113009,
-                    },
-                    zoom: 400000,
+                    }
+                    .into(),
+                    zoom: Zoom::new(400000.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
             (
@@ -252,8 +283,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: -1.25066,
                         im: 0.02012,
-                    },
-                    zoom: 75000,
+                    }
+                    .into(),
+                    zoom: Zoom::new(75000.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
             (
@@ -262,8 +296,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: -0.7771204433106587,
                         im: 0.1268572387863619,
-                    },
-                    zoom: 20000,
+                    }
+                    .into(),
+                    zoom: Zoom::new(20000.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
             (
@@ -272,8 +309,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: -0.74529,
                         im: 0.113075,
-                    },
-                    zoom: 80000,
+                    }
+                    .into(),
+                    zoom: Zoom::new(80000.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
             (
@@ -282,8 +322,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: -0.235125,
                         im: 0.827215,
-                    },
-                    zoom: 260000,
+                    }
+                    .into(),
+                    zoom: Zoom::new(260000.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
             (
@@ -292,8 +335,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: -0.7453,
                         im: 0.1127,
-                    },
-                    zoom: 10000,
+                    }
+                    .into(),
+                    zoom: Zoom::new(10000.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
             (
@@ -302,8 +348,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: -0.16,
                         im: 1.035,
-                    },
-                    zoom: 900,
+                    }
+                    .into(),
+                    zoom: Zoom::new(900.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
             (
@@ -312,8 +361,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: -0.16,
                         im: 1.035,
-                    },
-                    zoom: 700,
+                    }
+                    .into(),
+                    zoom: Zoom::new(700.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
             (
@@ -322,8 +374,11 @@ impl Default for Configuration {
                     point: Complex {
                         re: -1.25066,
                         im: 0.02012,
-                    },
-                    zoom: 200000,
+                    }
+                    .into(),
+                    zoom: Zoom::new(200000.),
+                    fractal: Fractal::Mandelbrot,
+                    rotation: 0.,
                 },
             ),
         ]);
@@ -0,0 +1,247 @@
+use crate::mandelbrot::Complex;
+use anyhow::{anyhow, Result};
+use num_bigint::BigInt;
+use num_traits::{Signed, Zero};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// the furthest a written coordinate's decimal exponent can reach, well past anything a view
+/// needs, so a typo can't ask for a power of 10 too big to hold
+const MAX_EXPONENT: i64 = 10_000;
+/// extra decimal places an offset is kept to beyond the distance it has to resolve
+const GUARD_PLACES: u32 = 3;
+
+/// one part of a point in the plane, kept as the decimal it was written as, so a centre can be
+/// given with as many digits as a deep zoom needs rather than the 17 or so an `f64` holds.
+///
+/// it's `digits` / 10^`places`, with no zeros on the end of `digits` while `places` is above 0,
+/// so equal coordinates are stored the same way.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coordinate {
+    digits: BigInt,
+    places: u32,
+}
+
+impl Coordinate {
+    /// the shortest decimal that reads back as `val`, which has to be finite
+    pub fn from_f64(val: f64) -> Self {
+        assert!(val.is_finite(), "coordinate must be finite");
+        format!("{:e}", val)
+            .parse()
+            .expect("floats print as valid coordinates")
+    }
+
+    /// the nearest `f64`, which is infinite past the range of one
+    pub fn to_f64(&self) -> f64 {
+        self.to_string()
+            .parse()
+            .expect("coordinates print as valid floats")
+    }
+
+    fn normalised(mut digits: BigInt, mut places: u32) -> Self {
+        let ten = BigInt::from(10);
+        while places > 0 && (&digits % &ten).is_zero() {
+            digits /= &ten;
+            places -= 1;
+        }
+        if digits.is_zero() {
+            places = 0;
+        }
+        Self { digits, places }
+    }
+
+    /// the digits of this coordinate written with `places` decimal places, rounding half away
+    /// from 0 if that's fewer than it has
+    fn digits_to(&self, places: u32) -> BigInt {
+        if places >= self.places {
+            &self.digits * BigInt::from(10).pow(places - self.places)
+        } else {
+            let divisor = BigInt::from(10).pow(self.places - places);
+            // division truncates towards 0, so half the divisor is added away from 0 first
+            let half = &divisor / 2 * self.digits.signum();
+            (&self.digits + half) / divisor
+        }
+    }
+
+    /// this coordinate with `bits` fractional bits, as `FixedPoint` numbers are stored, rounded
+    /// to the nearest
+    pub fn fixed(&self, bits: u64) -> BigInt {
+        let scaled = &self.digits << bits;
+        let divisor = BigInt::from(10).pow(self.places);
+        let half = &divisor / 2 * scaled.signum();
+        (scaled + half) / divisor
+    }
+
+    /// this coordinate moved by `delta`, which is rounded to a few more places than it takes to
+    /// resolve `resolution`, so repeated moves don't pile up all the digits of every `f64`
+    pub fn offset(&self, delta: f64, resolution: f64) -> Self {
+        let places =
+            (-resolution.log10()).ceil().clamp(0., MAX_EXPONENT as f64) as u32 + GUARD_PLACES;
+        let delta = Self::from_f64(delta);
+        let places = places.max(self.places);
+        Self::normalised(self.digits_to(places) + delta.digits_to(places), places)
+    }
+}
+
+/// prints every digit, without an exponent
+impl fmt::Display for Coordinate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.digits.is_negative() { "-" } else { "" };
+        let magnitude = self.digits.abs().to_string();
+        let places = self.places as usize;
+        if places == 0 {
+            return write!(f, "{}{}", sign, magnitude);
+        }
+        let padded = format!("{:0>width$}", magnitude, width = places + 1);
+        let (whole, fraction) = padded.split_at(padded.len() - places);
+        write!(f, "{}{}.{}", sign, whole, fraction)
+    }
+}
+
+/// parses a decimal like `-0.75`, `.5` or `2.81717921930775123456789e-1`, keeping every digit
+impl FromStr for Coordinate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let invalid = || anyhow!("couldn't parse {} as a coordinate", s);
+        let (mantissa, exponent) = match s.split_once(['e', 'E']) {
+            Some((mantissa, exponent)) => {
+                (mantissa, exponent.parse::<i64>().map_err(|_| invalid())?)
+            }
+            None => (s, 0),
+        };
+        if exponent.abs() > MAX_EXPONENT {
+            return Err(invalid());
+        }
+        let (negative, unsigned) = match mantissa.strip_prefix('-') {
+            Some(unsigned) => (true, unsigned),
+            None => (false, mantissa.strip_prefix('+').unwrap_or(mantissa)),
+        };
+        let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        let all_digits = format!("{}{}", whole, fraction);
+        if all_digits.is_empty() || !all_digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let digits: BigInt = all_digits.parse().map_err(|_| invalid())?;
+        let digits = if negative { -digits } else { digits };
+        let places = fraction.len() as i64 - exponent;
+        Ok(if places < 0 {
+            Self::normalised(digits * BigInt::from(10).pow(-places as u32), 0)
+        } else {
+            Self::normalised(digits, places as u32)
+        })
+    }
+}
+
+/// a coordinate as it's written in a config: a number for any coordinate an `f64` holds
+/// exactly as written, which is how every coordinate was written before they could be more
+/// precise, or a string otherwise
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum CoordinateRepr {
+    Number(f64),
+    Text(String),
+}
+
+impl Serialize for Coordinate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let val = self.to_f64();
+        if val.is_finite() && Self::from_f64(val) == *self {
+            CoordinateRepr::Number(val)
+        } else {
+            CoordinateRepr::Text(self.to_string())
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Coordinate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match CoordinateRepr::deserialize(deserializer)? {
+            CoordinateRepr::Number(val) if val.is_finite() => Ok(Self::from_f64(val)),
+            CoordinateRepr::Number(val) => Err(serde::de::Error::custom(format!(
+                "coordinate must be finite, not {}",
+                val
+            ))),
+            CoordinateRepr::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+/// a point in the plane made of `Coordinate`s, for the centres of views
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Point {
+    pub re: Coordinate,
+    pub im: Coordinate,
+}
+
+impl Point {
+    pub fn new(re: Coordinate, im: Coordinate) -> Self {
+        Self { re, im }
+    }
+
+    /// the nearest point of `f64`s
+    pub fn to_complex(&self) -> Complex {
+        Complex::new(self.re.to_f64(), self.im.to_f64())
+    }
+
+    /// this point moved by `delta`, kept to a few more places than it takes to resolve
+    /// `resolution`
+    pub fn offset(&self, delta: Complex, resolution: f64) -> Self {
+        Self {
+            re: self.re.offset(delta.re, resolution),
+            im: self.im.offset(delta.im, resolution),
+        }
+    }
+}
+
+impl From<Complex> for Point {
+    fn from(point: Complex) -> Self {
+        Self::new(
+            Coordinate::from_f64(point.re),
+            Coordinate::from_f64(point.im),
+        )
+    }
+}
+
+/// prints both parts in full, as `centre` takes them
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.re, self.im)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coordinates_keep_every_digit() {
+        let written = "-0.28171792193077512345678901234567890123";
+        let coordinate: Coordinate = written.parse().unwrap();
+        assert_eq!(coordinate.to_string(), written);
+        let yaml = serde_yaml::to_string(&coordinate).unwrap();
+        assert_eq!(
+            serde_yaml::from_str::<Coordinate>(&yaml).unwrap(),
+            coordinate
+        );
+        assert_eq!(
+            "2.5e-3".parse::<Coordinate>().unwrap().to_string(),
+            "0.0025"
+        );
+        assert_eq!(Coordinate::from_f64(0.1).to_f64(), 0.1);
+    }
+
+    #[test]
+    fn offsets_stay_precise() {
+        let start: Coordinate = "0.3".parse().unwrap();
+        let moved = start.offset(1e-40, 1e-40);
+        assert_eq!(
+            moved.to_string(),
+            "0.3000000000000000000000000000000000000001"
+        );
+        assert_eq!(moved.offset(-1e-40, 1e-40), start);
+    }
+}
@@ -0,0 +1,146 @@
+use image::{ImageBuffer, Pixel};
+use rayon::prelude::*;
+use std::ops::{Index, IndexMut};
+
+/// one value per pixel of an image, stored row by row from the top left in a single buffer
+#[derive(Clone, Debug, PartialEq)]
+pub struct Field<T> {
+    width: usize,
+    height: usize,
+    values: Vec<T>,
+}
+
+/// a rectangle of pixels within a `Field`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Tile {
+    /// a `width` by `height` image split into tiles of at most `size` by `size` pixels, row by
+    /// row
+    pub fn grid(width: usize, height: usize, size: usize) -> impl Iterator<Item = Tile> {
+        let size = size.max(1);
+        (0..height).step_by(size).flat_map(move |y| {
+            (0..width).step_by(size).map(move |x| Tile {
+                x,
+                y,
+                width: size.min(width - x),
+                height: size.min(height - y),
+            })
+        })
+    }
+
+    /// the (x, y) of each pixel in the tile, row by row
+    pub fn pixels(self) -> impl Iterator<Item = (usize, usize)> {
+        (self.y..self.y + self.height)
+            .flat_map(move |y| (self.x..self.x + self.width).map(move |x| (x, y)))
+    }
+}
+
+impl<T> Field<T> {
+    /// wraps `values`, which must hold `width * height` values row by row
+    pub fn new(width: usize, height: usize, values: Vec<T>) -> Self {
+        assert_eq!(values.len(), width * height, "field is the wrong size");
+        Self {
+            width,
+            height,
+            values,
+        }
+    }
+
+    /// fills the field with `f(x, y)`, working on rows in parallel
+    pub fn from_fn<F>(width: usize, height: usize, f: F) -> Self
+    where
+        T: Send,
+        F: Fn(usize, usize) -> T + Sync,
+    {
+        let values = (0..height)
+            .into_par_iter()
+            .flat_map_iter(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| f(x, y))
+            .collect();
+        Self::new(width, height, values)
+    }
+
+    /// fills the field a row at a time, for work which is cheaper done across a row at once
+    pub fn from_rows<F>(width: usize, height: usize, f: F) -> Self
+    where
+        T: Send,
+        F: Fn(usize) -> Vec<T> + Sync,
+    {
+        let values = (0..height).into_par_iter().flat_map_iter(&f).collect();
+        Self::new(width, height, values)
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    pub fn values_mut(&mut self) -> &mut [T] {
+        &mut self.values
+    }
+
+    pub fn into_values(self) -> Vec<T> {
+        self.values
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        self.values.chunks(self.width.max(1))
+    }
+
+    /// the field split into tiles of at most `size` by `size` pixels, row by row
+    pub fn tiles(&self, size: usize) -> impl Iterator<Item = Tile> {
+        Tile::grid(self.width, self.height, size)
+    }
+
+    /// applies `f` to every value in parallel
+    pub fn map<U, F>(&self, f: F) -> Field<U>
+    where
+        T: Sync,
+        U: Send,
+        F: Fn(&T) -> U + Sync,
+    {
+        Field::new(
+            self.width,
+            self.height,
+            self.values.par_iter().map(&f).collect(),
+        )
+    }
+
+    /// an image with `f` of each value as its pixels
+    pub fn to_image<P, F>(&self, f: F) -> ImageBuffer<P, Vec<P::Subpixel>>
+    where
+        P: Pixel,
+        F: Fn(&T) -> P,
+    {
+        ImageBuffer::from_fn(self.width as u32, self.height as u32, |x, y| {
+            f(&self[(x as usize, y as usize)])
+        })
+    }
+}
+
+impl<T> Index<(usize, usize)> for Field<T> {
+    type Output = T;
+
+    fn index(&self, (x, y): (usize, usize)) -> &T {
+        &self.values[y * self.width + x]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Field<T> {
+    fn index_mut(&mut self, (x, y): (usize, usize)) -> &mut T {
+        &mut self.values[y * self.width + x]
+    }
+}
@@ -1,4 +1,19 @@
+pub mod buddhabrot;
 pub mod config;
+pub mod coordinate;
+pub mod field;
+pub mod lighting;
 pub mod mandelbrot;
+pub mod newton;
+pub mod numeric;
 pub mod opts;
 pub mod palette;
+pub mod perturbation;
+pub mod raw;
+pub mod render;
+pub mod simd;
+pub mod subdivision;
+pub mod transform;
+pub mod trap;
+pub mod view;
+pub mod zoom;
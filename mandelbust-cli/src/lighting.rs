@@ -0,0 +1,81 @@
+use crate::mandelbrot::{Complex, Escape};
+use clap::ValueEnum;
+use image::Rgb;
+use serde::{Deserialize, Serialize};
+
+/// share of the brightness a pixel keeps when facing away from the light
+const AMBIENT: f64 = 0.2;
+/// strength and tightness of blinn-phong highlights
+const SPECULAR: f64 = 0.5;
+const SHININESS: i32 = 20;
+
+/// how light reflects off the surface the normals describe
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum LightingModel {
+    /// matte, diffuse lighting only
+    #[default]
+    Lambert,
+    /// diffuse lighting with shiny highlights
+    BlinnPhong,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Lighting {
+    pub model: LightingModel,
+    /// direction the light comes from in the plane of the image, in degrees anticlockwise from
+    /// the +re axis
+    pub angle: f64,
+    /// how far above the image the light is, relative to its distance across it
+    pub height: f64,
+}
+
+fn normalise([x, y, z]: [f64; 3]) -> [f64; 3] {
+    let length = (x * x + y * y + z * z).sqrt();
+    [x / length, y / length, z / length]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+impl Lighting {
+    /// how brightly an escaping pixel is lit, from its final iterate and that iterate's
+    /// derivative. the surface normal points along z / dz, which is normal to the level
+    /// curves of the potential, tilted 45 degrees out of the image. between `AMBIENT` and 1
+    /// for lambert; highlights can take blinn-phong above 1.
+    pub fn brightness(&self, z: Complex, derivative: Complex) -> f64 {
+        let u = z / derivative;
+        let u_abs = u.abs_value_sq().sqrt();
+        let normal = normalise([u.re / u_abs, u.im / u_abs, 1.]);
+        let angle = self.angle.to_radians();
+        let light = normalise([angle.cos(), angle.sin(), self.height]);
+        let diffuse = dot(normal, light).max(0.);
+        let mut brightness = AMBIENT + (1. - AMBIENT) * diffuse;
+        if self.model == LightingModel::BlinnPhong {
+            let [x, y, z] = light;
+            let halfway = normalise([x, y, z + 1.]);
+            brightness += SPECULAR * dot(normal, halfway).max(0.).powi(SHININESS);
+        }
+        if brightness.is_finite() {
+            brightness
+        } else {
+            1.
+        }
+    }
+
+    /// the brightness for a whole `Escape`, with points in the set and those without a
+    /// derivative left unlit at 1
+    pub fn escape_brightness(&self, escape: Escape, max_iters: usize) -> f64 {
+        match escape.derivative {
+            Some(derivative) if escape.iters < max_iters => self.brightness(escape.z, derivative),
+            _ => 1.,
+        }
+    }
+}
+
+/// scales a colour by `brightness`, saturating at white
+pub fn apply(colour: Rgb<u8>, brightness: f64) -> Rgb<u8> {
+    Rgb(colour
+        .0
+        .map(|channel| (channel as f64 * brightness).round().min(255.) as u8))
+}
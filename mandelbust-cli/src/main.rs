@@ -1,17 +1,56 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use image::ImageBuffer;
 use mandelbust_cli::opts::Cli;
+use mandelbust_cli::render::{ProgressBar, Renderer};
 
 fn main() -> Result<()> {
     let args = Cli::parse();
-    let hue_array = args.get_hue_array()?;
-    let (width, height) = args.resolution.to_dimensions();
-    let palette = args.get_palette()?;
-    let img = ImageBuffer::from_fn(width as u32, height as u32, |x, y| {
-        let frac = hue_array[x as usize][y as usize];
-        palette.value(frac)
-    });
+    if args.print_view {
+        println!("{}", args.describe_view()?);
+        return Ok(());
+    }
+    let raw = match &args.raw_out {
+        Some(path) => {
+            let bar = ProgressBar::new("iterating");
+            let renderer = Renderer::new().on_progress(|progress| bar.update(progress));
+            let raw = args.get_raw_data(&renderer)?;
+            bar.finish();
+            warn_glitched(&renderer);
+            raw.save(path)?;
+            Some(raw)
+        }
+        None => None,
+    };
+    if args.no_image {
+        return Ok(());
+    }
+
+    let img = match raw {
+        // no need to iterate again when the raw data has everything the image does
+        Some(raw) if args.colours_from_raw_data() => args.colour_raw_data(&raw)?,
+        _ => {
+            let bar = ProgressBar::new("rendering");
+            let renderer = Renderer::new().on_progress(|progress| bar.update(progress));
+            let (img, refined) = args.render(&renderer)?;
+            bar.finish();
+            warn_glitched(&renderer);
+            if args.adaptive {
+                println!("re-sampled {} edge pixels", refined);
+            }
+            img
+        }
+    };
     img.save(&args.out_file).context("problem saving image")?;
     Ok(())
 }
+
+/// points out pixels --deep-zoom couldn't fix, which may be coloured wrongly
+fn warn_glitched(renderer: &Renderer) {
+    let glitched = renderer.glitched();
+    if glitched > 0 {
+        eprintln!(
+            "warning: {} pixels were still glitched after the last reference orbit",
+            glitched
+        );
+    }
+}
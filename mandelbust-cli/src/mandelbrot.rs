@@ -1,27 +1,282 @@
+use crate::field::Field;
+use crate::numeric::Real;
 use crate::opts::Interval;
+use crate::transform::Transform;
+use crate::view::PixelMap;
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::ops::{Add, Div, Mul, Sub};
+use std::str::FromStr;
+
+/// bits of slack given to periodicity checking, as orbits settling onto a cycle only repeat
+/// up to rounding error
+const PERIODICITY_SLACK_BITS: u32 = 4;
+
+/// points are handed out to threads in runs this long by `generate_point_escape_counts`
+const POINT_CHUNK_SIZE: usize = 256;
+
+/// how close (squared) an orbit has to come back to an earlier value to count as a cycle: a few
+/// ulps at unit magnitude, so that attracting cycles are caught despite rounding
+pub(crate) fn periodicity_tolerance<T: Real>() -> T {
+    T::from_f64(2f64.powi(2 * (PERIODICITY_SLACK_BITS as i32 - T::MANTISSA_BITS as i32)))
+}
+
+/// the power d in z -> z^d + c
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exponent {
+    Integer(i32),
+    Real(f64),
+}
+
+impl From<f64> for Exponent {
+    fn from(d: f64) -> Self {
+        if d.fract() == 0. && d.abs() <= i32::MAX as f64 {
+            Exponent::Integer(d as i32)
+        } else {
+            Exponent::Real(d)
+        }
+    }
+}
+
+impl Exponent {
+    pub fn to_f64(self) -> f64 {
+        match self {
+            Exponent::Integer(n) => n as f64,
+            Exponent::Real(d) => d,
+        }
+    }
+
+    /// the rate at which escaping orbits grow, used to smooth escape counts. `None` when
+    /// orbits don't escape by repeated powering, i.e. for d <= 1
+    pub fn smoothing_degree(self) -> Option<f64> {
+        let d = self.to_f64();
+        (d > 1.).then_some(d)
+    }
+}
+
+/// escape-time fractals built from z -> z^d + c, with absolute values or conjugation applied
+/// either before or after raising to the power d
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum Fractal {
+    /// z^d + c
+    #[default]
+    Mandelbrot,
+    /// (|re z| + i|im z|)^d + c
+    BurningShip,
+    /// conj(z)^d + c
+    Tricorn,
+    /// |re(z^d)| + i im(z^d) + c
+    Celtic,
+    /// |re(z^d)| + i|im(z^d)| + c
+    Buffalo,
+}
+
+impl Fractal {
+    pub const ALL: [Fractal; 5] = [
+        Fractal::Mandelbrot,
+        Fractal::BurningShip,
+        Fractal::Tricorn,
+        Fractal::Celtic,
+        Fractal::Buffalo,
+    ];
+
+    pub fn iterate<T: Real>(self, z: Complex<T>, c: &Complex<T>, exponent: Exponent) -> Complex<T> {
+        match self {
+            Fractal::Mandelbrot => z.multibrot_iter(c, exponent),
+            Fractal::BurningShip => z.abs_parts().multibrot_iter(c, exponent),
+            Fractal::Tricorn => z.conj().multibrot_iter(c, exponent),
+            Fractal::Celtic => {
+                let w = z.multibrot_iter(&Complex::id(), exponent);
+                Complex::new(w.re.abs(), w.im) + *c
+            }
+            Fractal::Buffalo => z.multibrot_iter(&Complex::id(), exponent).abs_parts() + *c,
+        }
+    }
+}
+
+/// averages taken over an orbit for colouring, each term being between 0 and 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OrbitAverage {
+    /// 1/2 + sin(density * arg z)/2, which draws stripes radiating out from the set
+    Stripe { density: f64 },
+    /// where |z_n| falls between the bounds the triangle inequality puts on |z_(n-1)^d + c|
+    TriangleInequality,
+}
+
+impl OrbitAverage {
+    fn term(self, z: Complex, previous: Complex, c: Complex, exponent: Exponent) -> Option<f64> {
+        match self {
+            OrbitAverage::Stripe { density } => {
+                Some(0.5 + 0.5 * (density * z.im.atan2(z.re)).sin())
+            }
+            OrbitAverage::TriangleInequality => {
+                let power = previous.abs_value_sq().sqrt().powf(exponent.to_f64());
+                let c_abs = c.abs_value_sq().sqrt();
+                let lower = (power - c_abs).abs();
+                let upper = power + c_abs;
+                // the bounds meet when z_(n-1) or c is 0, e.g. on the first step from 0
+                (upper > lower)
+                    .then(|| ((z.abs_value_sq().sqrt() - lower) / (upper - lower)).clamp(0., 1.))
+            }
+        }
+    }
+}
+
+/// what iterating a single point found out
+#[derive(Clone, Copy, Debug)]
+pub struct Escape<T = f64> {
+    /// iterations taken to leave the bailout radius, or `max_iters` if the orbit never did
+    pub iters: usize,
+    /// the first point of the orbit outside the bailout radius, or wherever iteration stopped
+    pub z: Complex<T>,
+    /// the `OrbitAverage` asked for, over the orbit without and then with its last point
+    pub averages: Option<(f64, f64)>,
+    /// dz/dc, or dz/dz_0 for julia sets, if it was tracked
+    pub derivative: Option<Complex<T>>,
+}
+
+impl<T: Real> Escape<T> {
+    pub fn new(iters: usize, z: Complex<T>) -> Self {
+        Self {
+            iters,
+            z,
+            averages: None,
+            derivative: None,
+        }
+    }
+
+    pub fn to_f64(self) -> Escape {
+        Escape {
+            iters: self.iters,
+            z: self.z.to_f64(),
+            averages: self.averages,
+            derivative: self.derivative.map(Complex::to_f64),
+        }
+    }
+}
+
+impl Escape {
+    /// the orbit average, blended between its last two values by how far past the bailout
+    /// radius the orbit escaped, so that it doesn't jump where the escape count does
+    pub fn smooth_average(&self, bailout: f64, exponent: Exponent) -> Option<f64> {
+        let (without_last, with_last) = self.averages?;
+        let blend = match exponent.smoothing_degree() {
+            Some(degree) => {
+                let z_abs_ln = self.z.abs_value_sq().ln() / 2.;
+                (1. + (bailout.ln() / z_abs_ln).log(degree)).clamp(0., 1.)
+            }
+            None => 1.,
+        };
+        Some(without_last + (with_last - without_last) * blend)
+    }
+
+    /// how far the orbit started from the boundary, from its derivative, as
+    /// `Complex::distance_estimate` measures it
+    pub fn distance_estimate(&self, max_iters: usize) -> f64 {
+        match (self.iters, self.derivative) {
+            (0, _) => f64::INFINITY,
+            (iters, Some(derivative)) if iters < max_iters => {
+                let z_abs = self.z.abs_value_sq().sqrt();
+                let derivative_abs = derivative.abs_value_sq().sqrt();
+                z_abs * z_abs.ln() / derivative_abs
+            }
+            _ => 0.,
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
-pub struct Complex {
-    pub re: f64,
-    pub im: f64,
+pub struct Complex<T = f64> {
+    pub re: T,
+    pub im: T,
 }
 
-impl ToString for Complex {
+impl<T: Real> ToString for Complex<T> {
     fn to_string(&self) -> String {
-        format!("{:.2} + {:.2}i", self.re, self.im)
+        format!("{:.2} + {:.2}i", self.re.to_f64(), self.im.to_f64())
     }
 }
 
-impl Default for Complex {
+impl<T: Real> Default for Complex<T> {
     fn default() -> Self {
-        Self { re: 0., im: 0. }
+        Self::new(T::from_f64(0.), T::from_f64(0.))
+    }
+}
+
+impl<T: Real> Add for Complex<T> {
+    type Output = Self;
+
+    fn add(self, o: Self) -> Self {
+        Self::new(self.re + o.re, self.im + o.im)
+    }
+}
+
+impl<T: Real> Sub for Complex<T> {
+    type Output = Self;
+
+    fn sub(self, o: Self) -> Self {
+        Self::new(self.re - o.re, self.im - o.im)
+    }
+}
+
+impl<T: Real> Mul for Complex<T> {
+    type Output = Self;
+
+    fn mul(self, o: Self) -> Self {
+        Self::new(
+            self.re * o.re - self.im * o.im,
+            self.re * o.im + self.im * o.re,
+        )
+    }
+}
+
+impl<T: Real> Div for Complex<T> {
+    type Output = Self;
+
+    fn div(self, o: Self) -> Self {
+        let norm = o.abs_value_sq();
+        let numerator = self * o.conj();
+        Self::new(numerator.re / norm, numerator.im / norm)
+    }
+}
+
+/// parses numbers like `1.5`, `-2i`, `i` and `0.25-1e-3i`
+impl FromStr for Complex {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let parse = |part: &str| {
+            part.parse::<f64>()
+                .map_err(|_| anyhow!("couldn't parse {} as a complex number", s))
+        };
+        let Some(imaginary) = s.strip_suffix('i') else {
+            return Ok(Complex::new(parse(&s)?, 0.));
+        };
+        // the sign separating the two parts, skipping the sign of any exponent
+        let split = imaginary
+            .char_indices()
+            .rfind(|&(i, c)| {
+                (c == '+' || c == '-') && i > 0 && !imaginary[..i].ends_with(['e', 'E'])
+            })
+            .map(|(i, _)| i);
+        let (re, im) = match split {
+            Some(i) => (parse(&imaginary[..i])?, &imaginary[i..]),
+            None => (0., imaginary),
+        };
+        let im = match im {
+            "" | "+" => 1.,
+            "-" => -1.,
+            im => parse(im)?,
+        };
+        Ok(Complex::new(re, im))
     }
 }
 
-impl Complex {
-    pub fn new(re: f64, im: f64) -> Self {
+impl<T: Real> Complex<T> {
+    pub fn new(re: T, im: T) -> Self {
         Self { re, im }
     }
 
@@ -29,14 +284,67 @@ impl Complex {
         Self::default()
     }
 
+    pub fn from_f64(val: Complex) -> Self {
+        Self::new(T::from_f64(val.re), T::from_f64(val.im))
+    }
+
+    pub fn to_f64(self) -> Complex {
+        Complex::new(self.re.to_f64(), self.im.to_f64())
+    }
+
     pub fn mandelbrot_iter(&self, c: &Self) -> Self {
         Self {
             re: (self.re - self.im) * (self.re + self.im) + c.re,
-            im: 2. * self.re * self.im + c.im,
+            im: T::from_f64(2.) * self.re * self.im + c.im,
+        }
+    }
+
+    /// z -> z^d + c. for negative d, 0^d is taken to be 0 so the orbit of 0 still starts at c
+    pub fn multibrot_iter(&self, c: &Self, exponent: Exponent) -> Self {
+        let zero = T::from_f64(0.);
+        match exponent {
+            Exponent::Integer(2) => self.mandelbrot_iter(c),
+            _ if exponent.to_f64() < 0. && self.re == zero && self.im == zero => *c,
+            Exponent::Integer(n) => self.powi(n) + *c,
+            Exponent::Real(d) => self.powf(d) + *c,
         }
     }
 
-    pub fn abs_value_sq(&self) -> f64 {
+    pub fn powi(self, n: i32) -> Self {
+        let mut base = if n < 0 { self.inverse() } else { self };
+        let mut acc = Self::new(T::from_f64(1.), T::from_f64(0.));
+        let mut n = n.unsigned_abs();
+        while n > 0 {
+            if n & 1 == 1 {
+                acc = acc * base;
+            }
+            base = base * base;
+            n >>= 1;
+        }
+        acc
+    }
+
+    /// evaluated in polar form using `f64`s, so this gains nothing from a more precise `T`
+    pub fn powf(self, d: f64) -> Self {
+        let z = self.to_f64();
+        let r = z.abs_value_sq().powf(d / 2.);
+        let theta = z.im.atan2(z.re) * d;
+        Self::from_f64(Complex::new(r * theta.cos(), r * theta.sin()))
+    }
+
+    pub fn conj(self) -> Self {
+        Self::new(self.re, -self.im)
+    }
+
+    pub fn abs_parts(self) -> Self {
+        Self::new(self.re.abs(), self.im.abs())
+    }
+
+    pub fn scale(self, factor: T) -> Self {
+        Self::new(self.re * factor, self.im * factor)
+    }
+
+    pub fn abs_value_sq(&self) -> T {
         self.re * self.re + self.im * self.im
     }
 
@@ -45,101 +353,676 @@ impl Complex {
         Self::new(self.re / norm, -self.im / norm)
     }
 
-    pub fn escape_count(&self, z_0: Self, bound: f64, max_iters: usize) -> (usize, Self) {
-        if z_0.abs_value_sq() > bound {
-            return (0, z_0);
+    /// true if c lies in the main cardioid or the period-2 bulb of the mandelbrot set,
+    /// where every orbit from 0 is known to stay bounded
+    pub fn in_main_bulbs(&self) -> bool {
+        let quarter = T::from_f64(0.25);
+        let x = self.re - quarter;
+        let y_sq = self.im * self.im;
+        let q = x * x + y_sq;
+        let x_shifted = self.re + T::from_f64(1.);
+        q * (q + x) <= quarter * y_sq || x_shifted * x_shifted + y_sq <= T::from_f64(0.0625)
+    }
+
+    /// the result of `escape_count` when it can be given without iterating
+    pub(crate) fn known_escape_count(
+        &self,
+        z_0: Self,
+        fractal: Fractal,
+        exponent: Exponent,
+        bound: f64,
+        max_iters: usize,
+        interior_checks: bool,
+    ) -> Option<Escape<T>> {
+        if z_0.abs_value_sq() > T::from_f64(bound) {
+            return Some(Escape::new(0, z_0));
+        }
+        let zero = T::from_f64(0.);
+        if interior_checks
+            && fractal == Fractal::Mandelbrot
+            && exponent == Exponent::Integer(2)
+            && z_0.re == zero
+            && z_0.im == zero
+            && self.in_main_bulbs()
+        {
+            return Some(Escape::new(max_iters, z_0));
+        }
+        None
+    }
+
+    /// iterates from z_0 until the orbit leaves `bound` or `max_iters` is reached. with
+    /// `interior_checks`, orbits which are provably bounded stop early and report `max_iters`:
+    /// points in the main cardioid and period-2 bulb are skipped outright, and orbits which
+    /// land back on an earlier value (found with brent's cycle detection) are cut short
+    #[allow(clippy::too_many_arguments)]
+    pub fn escape_count(
+        &self,
+        z_0: Self,
+        fractal: Fractal,
+        exponent: Exponent,
+        bound: f64,
+        max_iters: usize,
+        interior_checks: bool,
+    ) -> Escape<T> {
+        self.escape_count_observed(
+            z_0,
+            fractal,
+            exponent,
+            bound,
+            max_iters,
+            interior_checks,
+            |_| (),
+        )
+    }
+
+    /// `escape_count`, calling `observe` on every point of the orbit which is still inside
+    /// `bound`, for colourings that depend on the whole orbit
+    #[allow(clippy::too_many_arguments)]
+    pub fn escape_count_observed<O: FnMut(Self)>(
+        &self,
+        z_0: Self,
+        fractal: Fractal,
+        exponent: Exponent,
+        bound: f64,
+        max_iters: usize,
+        interior_checks: bool,
+        mut observe: O,
+    ) -> Escape<T> {
+        if let Some(result) =
+            self.known_escape_count(z_0, fractal, exponent, bound, max_iters, interior_checks)
+        {
+            return result;
         }
-        let bound_sq = bound.powf(2.);
+        let bound_sq = T::from_f64(bound.powf(2.));
+        let tolerance = periodicity_tolerance::<T>();
+        let mut saved = z_0;
+        let mut period = 1;
+        let mut since_saved = 0;
         let mut z_iter = z_0;
         for iter in 1..=max_iters {
-            z_iter = z_iter.mandelbrot_iter(self);
+            z_iter = fractal.iterate(z_iter, self, exponent);
             if z_iter.abs_value_sq() > bound_sq {
-                return (iter, z_iter);
+                return Escape::new(iter, z_iter);
+            }
+            observe(z_iter);
+            if interior_checks {
+                if (z_iter - saved).abs_value_sq() < tolerance {
+                    return Escape::new(max_iters, z_iter);
+                }
+                since_saved += 1;
+                if since_saved == period {
+                    saved = z_iter;
+                    since_saved = 0;
+                    period *= 2;
+                }
+            }
+        }
+        Escape::new(max_iters, z_iter)
+    }
+
+    /// `escape_count`, also taking `average` over the orbit if there is one, and with
+    /// `derivative`, tracking the derivative as `escape_count_with_derivative` does for `julia`,
+    /// which only works for z -> z^d + c
+    #[allow(clippy::too_many_arguments)]
+    pub fn escape_count_tracked(
+        &self,
+        z_0: Self,
+        julia: bool,
+        fractal: Fractal,
+        exponent: Exponent,
+        bound: f64,
+        max_iters: usize,
+        interior_checks: bool,
+        average: Option<OrbitAverage>,
+        derivative: bool,
+    ) -> Escape<T> {
+        let c = self.to_f64();
+        let mut previous = z_0.to_f64();
+        let (mut sum, mut count, mut last) = (0., 0, 0.);
+        let mut add = |z: Complex| {
+            if let Some(term) = average.and_then(|average| average.term(z, previous, c, exponent)) {
+                sum += term;
+                count += 1;
+                last = term;
+            }
+            previous = z;
+        };
+        let observe = |z: Self| {
+            if average.is_some() {
+                add(z.to_f64())
             }
+        };
+        let mut escape = if derivative {
+            self.escape_count_observed_with_derivative(
+                z_0,
+                julia,
+                exponent,
+                bound,
+                max_iters,
+                interior_checks,
+                observe,
+            )
+        } else {
+            self.escape_count_observed(
+                z_0,
+                fractal,
+                exponent,
+                bound,
+                max_iters,
+                interior_checks,
+                observe,
+            )
+        };
+        if average.is_none() {
+            return escape;
+        }
+        // the escaping point isn't observed, but is part of the average
+        if 0 < escape.iters && escape.iters < max_iters {
+            add(escape.z.to_f64());
+        }
+        escape.averages = match count {
+            0 => None,
+            1 => Some((sum, sum)),
+            _ => Some(((sum - last) / (count - 1) as f64, sum / count as f64)),
+        };
+        escape
+    }
+
+    /// d * z^(d-1), the derivative of z^d
+    pub(crate) fn multibrot_derivative(self, exponent: Exponent) -> Self {
+        match exponent {
+            Exponent::Integer(n) => self.powi(n - 1).scale(T::from_f64(n as f64)),
+            Exponent::Real(d) => self.powf(d - 1.).scale(T::from_f64(d)),
+        }
+    }
+
+    /// `escape_count` for z -> z^d + c, also tracking the derivative of z with respect to c, or
+    /// to z_0 when `julia` is set, for distance estimation and lighting
+    #[allow(clippy::too_many_arguments)]
+    pub fn escape_count_with_derivative(
+        &self,
+        z_0: Self,
+        julia: bool,
+        exponent: Exponent,
+        bound: f64,
+        max_iters: usize,
+        interior_checks: bool,
+    ) -> Escape<T> {
+        self.escape_count_observed_with_derivative(
+            z_0,
+            julia,
+            exponent,
+            bound,
+            max_iters,
+            interior_checks,
+            |_| (),
+        )
+    }
+
+    /// `escape_count_with_derivative`, calling `observe` as `escape_count_observed` does
+    #[allow(clippy::too_many_arguments)]
+    pub fn escape_count_observed_with_derivative<O: FnMut(Self)>(
+        &self,
+        z_0: Self,
+        julia: bool,
+        exponent: Exponent,
+        bound: f64,
+        max_iters: usize,
+        interior_checks: bool,
+        mut observe: O,
+    ) -> Escape<T> {
+        let one = Complex::new(T::from_f64(1.), T::from_f64(0.));
+        let (mut derivative, step) = if julia {
+            (one, Complex::id())
+        } else {
+            (Complex::id(), one)
+        };
+        // each step of the derivative needs the point the orbit is stepping from
+        let mut previous = z_0;
+        let escape = self.escape_count_observed(
+            z_0,
+            Fractal::Mandelbrot,
+            exponent,
+            bound,
+            max_iters,
+            interior_checks,
+            |z| {
+                derivative = previous.multibrot_derivative(exponent) * derivative + step;
+                previous = z;
+                observe(z);
+            },
+        );
+        // the step onto the escaping point isn't observed
+        if 0 < escape.iters && escape.iters < max_iters {
+            derivative = previous.multibrot_derivative(exponent) * derivative + step;
         }
-        (max_iters, z_iter)
+        Escape {
+            derivative: Some(derivative),
+            ..escape
+        }
+    }
+
+    /// how far c, or z_0 for julia sets, is from the boundary of the set for z -> z^d + c,
+    /// using |z| ln|z| / |dz|. points in the set are 0 away, and points which start outside
+    /// the bailout are infinitely far away.
+    pub fn distance_estimate(
+        &self,
+        z_0: Self,
+        julia: bool,
+        exponent: Exponent,
+        bound: f64,
+        max_iters: usize,
+        interior_checks: bool,
+    ) -> f64 {
+        self.escape_count_with_derivative(z_0, julia, exponent, bound, max_iters, interior_checks)
+            .to_f64()
+            .distance_estimate(max_iters)
     }
 }
 
-pub fn generate_escape_counts<F>(
-    x_range: &Interval,
-    y_range: &Interval,
+/// iterates every pixel using the number type `T`. `pixel_map`'s offsets are relative to
+/// `centre`, so that types more precise than `f64` can resolve the difference between pixels.
+/// when `julia` is set, pixels are used as z_0 with a fixed c instead of as c, and either way
+/// they're moved by `transform` first. the plain mandelbrot iteration runs several pixels at
+/// once where the type and cpu allow it, unless `average` needs the whole orbit. with
+/// `derivative`, escapes carry it as `escape_count_with_derivative`'s do, for z -> z^d + c only.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_escape_counts<T, F, R>(
+    centre: Complex,
+    julia: Option<Complex>,
+    transform: &Transform,
+    fractal: Fractal,
+    exponent: Exponent,
+    pixel_map: &PixelMap,
     width: usize,
     height: usize,
     max_iters: usize,
     bailout: f64,
+    interior_checks: bool,
+    average: Option<OrbitAverage>,
+    derivative: bool,
     post_fn: F,
-) -> Vec<Vec<f64>>
+) -> Field<R>
 where
-    F: Fn(usize, Complex) -> f64 + std::marker::Sync,
+    T: Real,
+    R: Send,
+    F: Fn(Escape) -> R + std::marker::Sync,
 {
-    (0..width)
-        .into_par_iter()
-        .map(|x| {
-            (0..height)
-                .into_par_iter()
-                .map(|y| {
-                    let re = x_range.lerp(x as f64 / width as f64);
-                    let im = y_range.lerp(y as f64 / height as f64);
-                    let c = Complex::new(re, im);
-                    let (escape_count, escape_num) =
-                        c.escape_count(Complex::id(), bailout, max_iters);
-                    post_fn(escape_count, escape_num)
-                })
-                .collect()
-        })
+    let centre = Complex::<T>::from_f64(centre);
+    let julia = julia.map(Complex::<T>::from_f64);
+    Field::from_rows(width, height, |y| {
+        let points: Vec<(Complex<T>, Complex<T>)> = (0..width)
+            .map(|x| {
+                let point = transform
+                    .apply(centre + Complex::from_f64(pixel_map.offset(x as f64, y as f64)));
+                match julia {
+                    Some(c) => (c, point),
+                    None => (point, Complex::id()),
+                }
+            })
+            .collect();
+        escape_points(
+            &points,
+            julia.is_some(),
+            fractal,
+            exponent,
+            bailout,
+            max_iters,
+            interior_checks,
+            average,
+            derivative,
+        )
+        .into_iter()
+        .map(|escape| post_fn(escape.to_f64()))
         .collect()
+    })
 }
 
-pub fn normalise_escape_counts(escape_counts: &Vec<Vec<f64>>, max_iters: usize) -> Vec<Vec<f64>> {
-    escape_counts
-        .into_par_iter()
-        .map(|col| {
-            col.into_par_iter()
-                .map(|&val| val / max_iters as f64)
-                .collect()
+/// `generate_escape_counts` for points `offsets` away from `centre` instead of a grid of pixels
+#[allow(clippy::too_many_arguments)]
+pub fn generate_point_escape_counts<T, F, R>(
+    centre: Complex,
+    julia: Option<Complex>,
+    transform: &Transform,
+    fractal: Fractal,
+    exponent: Exponent,
+    offsets: &[Complex],
+    max_iters: usize,
+    bailout: f64,
+    interior_checks: bool,
+    average: Option<OrbitAverage>,
+    derivative: bool,
+    post_fn: F,
+) -> Vec<R>
+where
+    T: Real,
+    R: Send,
+    F: Fn(Escape) -> R + std::marker::Sync,
+{
+    let centre = Complex::<T>::from_f64(centre);
+    let julia = julia.map(Complex::<T>::from_f64);
+    offsets
+        .par_chunks(POINT_CHUNK_SIZE)
+        .flat_map_iter(|chunk| {
+            let points: Vec<(Complex<T>, Complex<T>)> = chunk
+                .iter()
+                .map(|&offset| {
+                    let point = transform.apply(centre + Complex::from_f64(offset));
+                    match julia {
+                        Some(c) => (c, point),
+                        None => (point, Complex::id()),
+                    }
+                })
+                .collect();
+            escape_points(
+                &points,
+                julia.is_some(),
+                fractal,
+                exponent,
+                bailout,
+                max_iters,
+                interior_checks,
+                average,
+                derivative,
+            )
+            .into_iter()
+            .map(|escape| post_fn(escape.to_f64()))
         })
         .collect()
 }
 
-pub fn generate_hist_counts(
-    escape_counts: &Vec<Vec<f64>>,
+/// iterates each (c, z_0) in `points`, several at once for the plain mandelbrot iteration
+/// where the type and cpu allow it, unless `average` or `derivative` needs the whole orbit
+#[allow(clippy::too_many_arguments)]
+fn escape_points<T: Real>(
+    points: &[(Complex<T>, Complex<T>)],
+    julia: bool,
+    fractal: Fractal,
+    exponent: Exponent,
+    bailout: f64,
     max_iters: usize,
-    total_points: usize,
-) -> Vec<Vec<f64>> {
-    let pixels_per_iter: &mut Vec<usize> = &mut vec![0; max_iters + 1];
-    escape_counts.iter().for_each(|col| {
-        col.iter()
-            .for_each(|&count| pixels_per_iter[count as usize] += 1)
-    });
-
-    let iter_hist: Vec<usize> = (0..=max_iters)
-        .into_par_iter()
-        .map(|iter| pixels_per_iter[0..=iter].par_iter().sum())
-        .collect();
-
-    let total_points = total_points as f64;
-    escape_counts
-        .into_par_iter()
-        .map(|col| {
-            col.into_par_iter()
-                .map(|&count| {
-                    let floored_count = count as usize;
-                    if (floored_count) < max_iters {
-                        let interval = Interval {
-                            lower: iter_hist[floored_count] as f64,
-                            upper: iter_hist[floored_count + 1] as f64,
-                        };
-                        interval.lerp(decimal_part(count)) / total_points
-                    } else {
-                        iter_hist[floored_count] as f64 / total_points
-                    }
+    interior_checks: bool,
+    average: Option<OrbitAverage>,
+    derivative: bool,
+) -> Vec<Escape<T>> {
+    let vectorisable = fractal == Fractal::Mandelbrot
+        && exponent == Exponent::Integer(2)
+        && average.is_none()
+        && !derivative;
+    vectorisable
+        .then(|| T::mandelbrot_escape_counts(points, bailout, max_iters, interior_checks))
+        .flatten()
+        .unwrap_or_else(|| {
+            points
+                .par_iter()
+                .map(|(c, z_0)| {
+                    c.escape_count_tracked(
+                        *z_0,
+                        julia,
+                        fractal,
+                        exponent,
+                        bailout,
+                        max_iters,
+                        interior_checks,
+                        average,
+                        derivative,
+                    )
                 })
                 .collect()
         })
+}
+
+/// calls `f` with (c, z_0) for every pixel, laid out as in `generate_escape_counts`
+#[allow(clippy::too_many_arguments)]
+pub fn map_pixels<T, R, F>(
+    centre: Complex,
+    julia: Option<Complex>,
+    transform: &Transform,
+    pixel_map: &PixelMap,
+    width: usize,
+    height: usize,
+    f: F,
+) -> Field<R>
+where
+    T: Real,
+    R: Send,
+    F: Fn(Complex<T>, Complex<T>) -> R + Sync,
+{
+    let centre = Complex::<T>::from_f64(centre);
+    let julia = julia.map(Complex::<T>::from_f64);
+    Field::from_fn(width, height, |x, y| {
+        let point =
+            transform.apply(centre + Complex::from_f64(pixel_map.offset(x as f64, y as f64)));
+        match julia {
+            Some(c) => f(c, point),
+            None => f(point, Complex::id()),
+        }
+    })
+}
+
+/// calls `f` with (c, z_0) for each point `offsets` away from `centre`, for sampling pixels
+/// which aren't on a grid
+pub fn map_points<T, R, F>(
+    centre: Complex,
+    julia: Option<Complex>,
+    transform: &Transform,
+    offsets: &[Complex],
+    f: F,
+) -> Vec<R>
+where
+    T: Real,
+    R: Send,
+    F: Fn(Complex<T>, Complex<T>) -> R + Sync,
+{
+    let centre = Complex::<T>::from_f64(centre);
+    let julia = julia.map(Complex::<T>::from_f64);
+    offsets
+        .par_iter()
+        .map(|&offset| {
+            let point = transform.apply(centre + Complex::from_f64(offset));
+            match julia {
+                Some(c) => f(c, point),
+                None => f(point, Complex::id()),
+            }
+        })
         .collect()
 }
 
+/// estimates how far each pixel is from the boundary of the mandelbrot or julia set for
+/// z -> z^d + c, using |z| ln|z| / |dz|, measured in pixels. points in the set are 0 away,
+/// and points which start outside the bailout are infinitely far away. `post_fn` is given each
+/// distance along with the `Escape` it came from, which carries the derivative.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_distance_estimates<T, F, R>(
+    centre: Complex,
+    julia: Option<Complex>,
+    exponent: Exponent,
+    pixel_map: &PixelMap,
+    width: usize,
+    height: usize,
+    max_iters: usize,
+    bailout: f64,
+    interior_checks: bool,
+    post_fn: F,
+) -> Field<R>
+where
+    T: Real,
+    R: Send,
+    F: Fn(f64, Escape) -> R + Sync,
+{
+    let pixel_spacing = pixel_map.spacing();
+    map_pixels(
+        centre,
+        julia,
+        &Transform::default(),
+        pixel_map,
+        width,
+        height,
+        |c: Complex<T>, z_0| {
+            let escape = c
+                .escape_count_with_derivative(
+                    z_0,
+                    julia.is_some(),
+                    exponent,
+                    bailout,
+                    max_iters,
+                    interior_checks,
+                )
+                .to_f64();
+            post_fn(escape.distance_estimate(max_iters) / pixel_spacing, escape)
+        },
+    )
+}
+
+pub fn normalise_escape_counts(escape_counts: &Field<f64>, max_iters: usize) -> Field<f64> {
+    escape_counts.map(|&val| val / max_iters as f64)
+}
+
+/// how histogram colouring spreads the palette over escape counts
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Equalisation {
+    /// each escape count gets a share of the palette in proportion to how many pixels have it
+    #[default]
+    Count,
+    /// each pixel's hue is its rank among all the pixels, so smooth counts are spread evenly too
+    Rank,
+    /// like count, but by the log of how many pixels have each count, so counts covering much
+    /// of the image don't take over the palette
+    Log,
+}
+
+/// the ways histogram colouring can be tuned
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HistogramOptions {
+    pub equalisation: Equalisation,
+    /// leave points in the set, at `max_iters`, out of the histogram, so the palette is spread
+    /// over escaping points alone. points in the set still get a hue of 1.
+    pub exclude_interior: bool,
+    /// how much of the hue comes from the histogram, between 0 and 1, with the rest from the
+    /// escape count over `max_iters`
+    pub blend: f64,
+}
+
+impl Default for HistogramOptions {
+    fn default() -> Self {
+        Self {
+            equalisation: Equalisation::Count,
+            exclude_interior: false,
+            blend: 1.,
+        }
+    }
+}
+
+/// how many pixels escaped by each iteration count, for spreading colours evenly over them
+pub struct Histogram {
+    /// the weight of every count up to and including each one
+    cumulative: Vec<f64>,
+    /// every value counted, in order, for `Equalisation::Rank`
+    sorted: Vec<f64>,
+    max_iters: usize,
+    options: HistogramOptions,
+}
+
+impl Histogram {
+    pub fn new(escape_counts: &Field<f64>, max_iters: usize, options: HistogramOptions) -> Self {
+        let counted = |count: f64| !options.exclude_interior || (count as usize) < max_iters;
+        if options.equalisation == Equalisation::Rank {
+            let mut sorted: Vec<f64> = escape_counts
+                .values()
+                .iter()
+                .copied()
+                .filter(|&count| counted(count))
+                .collect();
+            sorted.par_sort_unstable_by(f64::total_cmp);
+            return Self {
+                cumulative: Vec::new(),
+                sorted,
+                max_iters,
+                options,
+            };
+        }
+
+        let mut pixels_per_iter = vec![0usize; max_iters + 1];
+        escape_counts
+            .values()
+            .iter()
+            .filter(|&&count| counted(count))
+            .for_each(|&count| pixels_per_iter[count as usize] += 1);
+        let weights: Vec<f64> = pixels_per_iter
+            .iter()
+            .map(|&pixels| match options.equalisation {
+                Equalisation::Log => (pixels as f64).ln_1p(),
+                _ => pixels as f64,
+            })
+            .collect();
+
+        let cumulative: Vec<f64> = weights
+            .iter()
+            .scan(0., |sum, weight| {
+                *sum += weight;
+                Some(*sum)
+            })
+            .collect();
+
+        Self {
+            cumulative,
+            sorted: Vec::new(),
+            max_iters,
+            options,
+        }
+    }
+
+    /// the share of pixels which escaped before `count`, blended with the plain escape count
+    /// as the options ask
+    pub fn hue(&self, count: f64) -> f64 {
+        let equalised = if self.options.exclude_interior && count as usize >= self.max_iters {
+            1.
+        } else if self.options.equalisation == Equalisation::Rank {
+            self.rank(count)
+        } else {
+            self.share(count)
+        };
+        let linear = (count / self.max_iters as f64).min(1.);
+        self.options.blend * equalised + (1. - self.options.blend) * linear
+    }
+
+    fn share(&self, count: f64) -> f64 {
+        let total = self.cumulative[self.max_iters];
+        if total == 0. {
+            return 1.;
+        }
+        let floored_count = count as usize;
+        if (floored_count) < self.max_iters {
+            let interval = Interval {
+                lower: self.cumulative[floored_count],
+                upper: self.cumulative[floored_count + 1],
+            };
+            interval.lerp(decimal_part(count)) / total
+        } else {
+            self.cumulative[floored_count] / total
+        }
+    }
+
+    /// where `count` falls among the sorted values, with ties taking the middle of their run
+    fn rank(&self, count: f64) -> f64 {
+        if self.sorted.is_empty() {
+            return 1.;
+        }
+        let below = self.sorted.partition_point(|&value| value < count);
+        let not_above = self.sorted.partition_point(|&value| value <= count);
+        (below + not_above) as f64 / 2. / self.sorted.len() as f64
+    }
+}
+
+pub fn generate_hist_counts(
+    escape_counts: &Field<f64>,
+    max_iters: usize,
+    options: HistogramOptions,
+) -> Field<f64> {
+    let histogram = Histogram::new(escape_counts, max_iters, options);
+    escape_counts.map(|&count| histogram.hue(count))
+}
+
 fn decimal_part(float: f64) -> f64 {
     float - (float as usize) as f64
 }
@@ -0,0 +1,195 @@
+use crate::field::Field;
+use crate::mandelbrot::Complex;
+use crate::view::PixelMap;
+use anyhow::{anyhow, Result};
+
+/// how close (squared) an iterate has to get to a root to count as converged
+const CONVERGENCE_TOLERANCE: f64 = 1e-18;
+const ROOT_FINDING_ITERS: usize = 1000;
+/// roots closer together than this are taken as one repeated root. durand-kerner only pins a
+/// root repeated k times down to around the k-th root of the rounding error, spreading its
+/// copies out by up to about this much for a triple root.
+const REPEATED_ROOT_DISTANCE: f64 = 1e-4;
+
+#[derive(Clone, Debug)]
+pub struct Polynomial {
+    /// coefficients from the constant term upwards
+    coefficients: Vec<Complex>,
+}
+
+/// one of a polynomial's distinct roots
+#[derive(Clone, Copy, Debug)]
+pub struct Root {
+    pub value: Complex,
+    /// how many times the root is repeated
+    pub multiplicity: usize,
+}
+
+impl Root {
+    /// how close (squared) an iterate has to get to the root to count as converged. newton's
+    /// method slows down near repeated roots and stalls around the k-th root of the rounding
+    /// error, so the tolerance is loosened to match.
+    fn tolerance(&self) -> f64 {
+        CONVERGENCE_TOLERANCE.powf(1. / self.multiplicity as f64)
+    }
+
+    /// merges copies of the same root, and those within `REPEATED_ROOT_DISTANCE` of each other,
+    /// into one root each, keeping the order they first appear in
+    pub fn distinct(roots: &[Complex]) -> Vec<Root> {
+        let mut groups: Vec<Vec<Complex>> = Vec::new();
+        for &root in roots {
+            let near = |group: &&mut Vec<Complex>| {
+                (group[0] - root).abs_value_sq() < REPEATED_ROOT_DISTANCE.powi(2)
+            };
+            match groups.iter_mut().find(near) {
+                Some(group) => group.push(root),
+                None => groups.push(vec![root]),
+            }
+        }
+        groups
+            .into_iter()
+            .map(|group| {
+                let sum = group.iter().fold(Complex::id(), |sum, &root| sum + root);
+                Root {
+                    // the copies surround the true root, so their mean is closer than any one
+                    value: sum.scale(1. / group.len() as f64),
+                    multiplicity: group.len(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// the root a pixel converged to, and how many newton steps it took
+#[derive(Clone, Copy, Debug)]
+pub struct Basin {
+    pub root: usize,
+    pub iters: usize,
+}
+
+impl Polynomial {
+    pub fn from_coefficients(coefficients: &[Complex]) -> Result<Self> {
+        let degree = coefficients
+            .iter()
+            .rposition(|c| c.abs_value_sq() != 0.)
+            .unwrap_or(0);
+        if degree < 2 {
+            return Err(anyhow!(
+                "newton fractals need a polynomial of degree 2 or more"
+            ));
+        }
+        Ok(Self {
+            coefficients: coefficients[..=degree].to_vec(),
+        })
+    }
+
+    pub fn from_roots(roots: &[Complex]) -> Result<Self> {
+        let coefficients = roots
+            .iter()
+            .fold(vec![Complex::new(1., 0.)], |coefficients, &root| {
+                // multiply by (z - root)
+                let mut next = vec![Complex::id(); coefficients.len() + 1];
+                for (i, &coefficient) in coefficients.iter().enumerate() {
+                    next[i + 1] = next[i + 1] + coefficient;
+                    next[i] = next[i] - coefficient * root;
+                }
+                next
+            });
+        Self::from_coefficients(&coefficients)
+    }
+
+    pub fn degree(&self) -> usize {
+        self.coefficients.len() - 1
+    }
+
+    /// the polynomial and its derivative at z, by horner's method
+    pub fn eval(&self, z: Complex) -> (Complex, Complex) {
+        self.coefficients.iter().rev().fold(
+            (Complex::id(), Complex::id()),
+            |(value, derivative), &coefficient| (value * z + coefficient, derivative * z + value),
+        )
+    }
+
+    /// finds every root at once using the durand-kerner method, with repeated roots merged
+    pub fn roots(&self) -> Vec<Root> {
+        let leading = *self.coefficients.last().unwrap();
+        let monic = Polynomial {
+            coefficients: self.coefficients.iter().map(|&c| c / leading).collect(),
+        };
+        // the usual starting guesses, powers of a number which is neither real nor a root of unity
+        let seed = Complex::new(0.4, 0.9);
+        let mut roots: Vec<Complex> = (0..self.degree())
+            .scan(Complex::new(1., 0.), |power, _| {
+                *power = *power * seed;
+                Some(*power)
+            })
+            .collect();
+        for _ in 0..ROOT_FINDING_ITERS {
+            let mut largest_step: f64 = 0.;
+            for i in 0..roots.len() {
+                let denominator = roots
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .fold(Complex::new(1., 0.), |acc, (_, &other)| {
+                        acc * (roots[i] - other)
+                    });
+                // copies of a repeated root can land on each other, leaving nothing to divide by
+                if denominator.abs_value_sq() == 0. {
+                    continue;
+                }
+                let step = monic.eval(roots[i]).0 / denominator;
+                roots[i] = roots[i] - step;
+                largest_step = largest_step.max(step.abs_value_sq());
+            }
+            if largest_step < CONVERGENCE_TOLERANCE {
+                break;
+            }
+        }
+        Root::distinct(&roots)
+    }
+
+    fn basin(
+        &self,
+        roots: &[Root],
+        tolerances: &[f64],
+        z_0: Complex,
+        max_iters: usize,
+    ) -> Option<Basin> {
+        let mut z = z_0;
+        for iters in 0..max_iters {
+            if let Some(root) = roots
+                .iter()
+                .zip(tolerances)
+                .position(|(root, &tolerance)| (z - root.value).abs_value_sq() < tolerance)
+            {
+                return Some(Basin { root, iters });
+            }
+            let (value, derivative) = self.eval(z);
+            if derivative.abs_value_sq() == 0. {
+                return None;
+            }
+            z = z - value / derivative;
+        }
+        None
+    }
+}
+
+/// runs newton's method from every pixel, using the same view mapping as
+/// `generate_escape_counts`. pixels which don't settle on one of `roots` are `None`
+#[allow(clippy::too_many_arguments)]
+pub fn generate_basins(
+    polynomial: &Polynomial,
+    roots: &[Root],
+    centre: Complex,
+    pixel_map: &PixelMap,
+    width: usize,
+    height: usize,
+    max_iters: usize,
+) -> Field<Option<Basin>> {
+    let tolerances: Vec<f64> = roots.iter().map(Root::tolerance).collect();
+    Field::from_fn(width, height, |x, y| {
+        let point = centre + pixel_map.offset(x as f64, y as f64);
+        polynomial.basin(roots, &tolerances, point, max_iters)
+    })
+}
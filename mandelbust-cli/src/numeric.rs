@@ -0,0 +1,191 @@
+use crate::mandelbrot::{Complex, Escape};
+use crate::simd;
+use std::fmt::Debug;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// a real number type the iteration core can be run with
+pub trait Real:
+    Copy
+    + Debug
+    + Send
+    + Sync
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    /// bits of precision in the significand
+    const MANTISSA_BITS: u32;
+
+    fn from_f64(val: f64) -> Self;
+
+    fn to_f64(self) -> f64;
+
+    fn abs(self) -> Self {
+        if self < Self::from_f64(0.) {
+            -self
+        } else {
+            self
+        }
+    }
+
+    /// `Complex::escape_count` for z -> z^2 + c on a batch of (c, z_0) pairs at once, for types
+    /// with a vectorised loop the cpu supports. `None` means iterate them one by one instead
+    fn mandelbrot_escape_counts(
+        _points: &[(Complex<Self>, Complex<Self>)],
+        _bound: f64,
+        _max_iters: usize,
+        _interior_checks: bool,
+    ) -> Option<Vec<Escape<Self>>> {
+        None
+    }
+}
+
+impl Real for f32 {
+    const MANTISSA_BITS: u32 = f32::MANTISSA_DIGITS;
+
+    fn from_f64(val: f64) -> Self {
+        val as f32
+    }
+
+    fn to_f64(self) -> f64 {
+        self as f64
+    }
+
+    fn mandelbrot_escape_counts(
+        points: &[(Complex<Self>, Complex<Self>)],
+        bound: f64,
+        max_iters: usize,
+        interior_checks: bool,
+    ) -> Option<Vec<Escape<Self>>> {
+        simd::escape_counts_f32(points, bound, max_iters, interior_checks)
+    }
+}
+
+impl Real for f64 {
+    const MANTISSA_BITS: u32 = f64::MANTISSA_DIGITS;
+
+    fn from_f64(val: f64) -> Self {
+        val
+    }
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn mandelbrot_escape_counts(
+        points: &[(Complex<Self>, Complex<Self>)],
+        bound: f64,
+        max_iters: usize,
+        interior_checks: bool,
+    ) -> Option<Vec<Escape<Self>>> {
+        simd::escape_counts_f64(points, bound, max_iters, interior_checks)
+    }
+}
+
+/// an unevaluated sum of two `f64`s, giving roughly 106 bits of precision.
+/// see Dekker (1971) and Hida, Li & Bailey, "Library for Double-Double and Quad-Double Arithmetic"
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd)]
+pub struct DoubleDouble {
+    hi: f64,
+    lo: f64,
+}
+
+// 2^27 + 1, used to split an f64 into two non-overlapping halves
+const SPLITTER: f64 = 134217729.;
+
+/// a + b exactly, assuming |a| >= |b|
+fn quick_two_sum(a: f64, b: f64) -> DoubleDouble {
+    let hi = a + b;
+    let lo = b - (hi - a);
+    DoubleDouble { hi, lo }
+}
+
+/// a + b exactly
+fn two_sum(a: f64, b: f64) -> DoubleDouble {
+    let hi = a + b;
+    let b_virtual = hi - a;
+    let lo = (a - (hi - b_virtual)) + (b - b_virtual);
+    DoubleDouble { hi, lo }
+}
+
+fn split(a: f64) -> (f64, f64) {
+    let t = SPLITTER * a;
+    let hi = t - (t - a);
+    (hi, a - hi)
+}
+
+/// a * b exactly
+fn two_prod(a: f64, b: f64) -> DoubleDouble {
+    let hi = a * b;
+    let (a_hi, a_lo) = split(a);
+    let (b_hi, b_lo) = split(b);
+    let lo = ((a_hi * b_hi - hi) + a_hi * b_lo + a_lo * b_hi) + a_lo * b_lo;
+    DoubleDouble { hi, lo }
+}
+
+impl Add for DoubleDouble {
+    type Output = Self;
+
+    fn add(self, o: Self) -> Self {
+        let s = two_sum(self.hi, o.hi);
+        let t = two_sum(self.lo, o.lo);
+        let s = quick_two_sum(s.hi, s.lo + t.hi);
+        quick_two_sum(s.hi, s.lo + t.lo)
+    }
+}
+
+impl Neg for DoubleDouble {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+}
+
+impl Sub for DoubleDouble {
+    type Output = Self;
+
+    fn sub(self, o: Self) -> Self {
+        self + -o
+    }
+}
+
+impl Mul for DoubleDouble {
+    type Output = Self;
+
+    fn mul(self, o: Self) -> Self {
+        let p = two_prod(self.hi, o.hi);
+        quick_two_sum(p.hi, p.lo + (self.hi * o.lo + self.lo * o.hi))
+    }
+}
+
+impl Div for DoubleDouble {
+    type Output = Self;
+
+    fn div(self, o: Self) -> Self {
+        let q1 = self.hi / o.hi;
+        let r = self - o * Self::from_f64(q1);
+        let q2 = r.hi / o.hi;
+        let r = r - o * Self::from_f64(q2);
+        let q3 = r.hi / o.hi;
+        quick_two_sum(q1, q2) + Self::from_f64(q3)
+    }
+}
+
+impl Real for DoubleDouble {
+    const MANTISSA_BITS: u32 = 2 * f64::MANTISSA_DIGITS;
+
+    fn from_f64(val: f64) -> Self {
+        Self { hi: val, lo: 0. }
+    }
+
+    fn to_f64(self) -> f64 {
+        self.hi + self.lo
+    }
+}
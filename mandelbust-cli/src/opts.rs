@@ -1,9 +1,31 @@
+use crate::buddhabrot::{generate_densities, normalise_densities};
 use crate::config::Configuration;
-use crate::mandelbrot::Complex;
-use crate::mandelbrot::{generate_escape_counts, generate_hist_counts, normalise_escape_counts};
-use crate::palette::ColorPalette;
-use anyhow::Result;
+use crate::coordinate::{Coordinate, Point};
+use crate::field::Field;
+use crate::lighting::{self, Lighting, LightingModel};
+use crate::mandelbrot::{
+    generate_distance_estimates, generate_escape_counts, generate_hist_counts,
+    generate_point_escape_counts, map_points, normalise_escape_counts, Equalisation, Histogram,
+    HistogramOptions,
+};
+use crate::mandelbrot::{Complex, Escape, Exponent, Fractal, OrbitAverage};
+use crate::newton::{generate_basins, Polynomial, Root};
+use crate::numeric::{DoubleDouble, Real};
+use crate::palette::{hsv_to_rgb, ColorPalette};
+use crate::perturbation::generate_perturbed_escape_counts;
+use crate::raw::{self, RawData, RawEscape, RawParams};
+use crate::render::Renderer;
+use crate::subdivision::generate_subdivided_escape_counts;
+use crate::transform::{Transform, TransformKind};
+use crate::trap::{generate_trap_distances, OrbitTrap, TrapShape};
+use crate::view::{fit_corners, Orientation, PixelMap};
+use crate::zoom::Zoom;
+use anyhow::{anyhow, Result};
 use clap::{Parser, Subcommand, ValueEnum};
+use image::{ImageBuffer, Rgb, RgbImage};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rayon::prelude::*;
 
 static PALETTE_HELP: &str = "color palette to use in output image; 
 defaults include electric, warm, and greyscale;
@@ -24,6 +46,35 @@ pub struct Cli {
     /// resolution of the output image.
     #[arg(short, long, value_enum, default_value_t = Resolution::High)]
     pub resolution: Resolution,
+    /// width of the output image in pixels, overriding --resolution's; on its own, the height
+    /// keeps --resolution's aspect ratio
+    #[arg(long)]
+    pub width: Option<usize>,
+    /// height of the output image in pixels, overriding --resolution's; on its own, the width
+    /// keeps --resolution's aspect ratio
+    #[arg(long)]
+    pub height: Option<usize>,
+    /// degrees to turn the view anticlockwise about its centre; defaults to the named point's
+    /// rotation for centre-string, and 0 otherwise
+    #[arg(long, allow_negative_numbers = true)]
+    pub rotation: Option<f64>,
+    /// mirror the view left to right
+    #[arg(long)]
+    pub flip_horizontal: bool,
+    /// mirror the view top to bottom, so positive imaginary is down
+    #[arg(long)]
+    pub flip_vertical: bool,
+    /// move each pixel's point before iterating it, e.g. invert for the inverted mandelbrot
+    /// set; not available with --deep-zoom, --lighting or distance estimation
+    #[arg(long, value_enum, default_value_t = TransformKind::None)]
+    pub transform: TransformKind,
+    /// coefficients a,b,c,d of --transform mobius, e.g. `0,1,1,0` for 1/p
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    pub mobius: Vec<Complex>,
+    /// degrees to tip the globe of --transform stereographic back, bringing the point at
+    /// infinity round to face the viewer at 180
+    #[arg(long, default_value_t = 0., allow_negative_numbers = true)]
+    pub sphere_tilt: f64,
     #[arg(
         short,
         long,
@@ -36,67 +87,1243 @@ pub struct Cli {
     /// algorithm to plot the image using
     #[arg(short, long, value_enum, default_value_t = PlottingAlgorithm::Histogram)]
     pub algorithm: PlottingAlgorithm,
+    /// fractal to plot; defaults to mandelbrot, or the landmark's fractal for centre-string
+    #[arg(short, long, value_enum)]
+    pub fractal: Option<Fractal>,
+    /// exponent d in z -> z^d + c; may be negative or fractional
+    #[arg(short, long, default_value_t = 2., allow_negative_numbers = true)]
+    pub exponent: f64,
+    /// use perturbation theory to render zooms beyond the precision of f64
+    #[arg(long)]
+    pub deep_zoom: bool,
+    /// number type used for iteration; ignored when rendering with --deep-zoom
+    #[arg(long, value_enum, default_value_t = Precision::Auto)]
+    pub precision: Precision,
+    /// iterate interior points in full instead of stopping early once they're known to be
+    /// inside, e.g. for benchmarking
+    #[arg(long)]
+    pub no_interior_checks: bool,
+    /// fill regions whose border all escapes at the same count instead of iterating every
+    /// pixel, which is much faster for views with a lot of interior; ignored with --deep-zoom
+    #[arg(long)]
+    pub subdivide: bool,
+    /// width in pixels of the boundary drawn by --algorithm distance-estimate
+    #[arg(long, default_value_t = 1.)]
+    pub thickness: f64,
+    /// shape used by --algorithm orbit-trap
+    #[arg(long, value_enum, default_value_t = TrapShape::Point)]
+    pub trap: TrapShape,
+    /// centre of the orbit trap, e.g. `0.5-0.25i`
+    #[arg(long, default_value = "0", allow_hyphen_values = true)]
+    pub trap_centre: Complex,
+    /// how close orbits have to pass the trap to be coloured by it; also the circle's radius
+    #[arg(long, default_value_t = 1.)]
+    pub trap_size: f64,
+    /// number of stripes drawn by --algorithm stripe-average
+    #[arg(long, default_value_t = 5.)]
+    pub stripe_density: f64,
+    /// how --algorithm histogram and smooth-histogram spread the palette over escape counts
+    #[arg(long, value_enum, default_value_t = Equalisation::Count)]
+    pub hist_equalisation: Equalisation,
+    /// leave points in the set out of the histogram, so views with a lot of interior don't
+    /// squash the palette into the few counts outside it
+    #[arg(long)]
+    pub hist_exclude_interior: bool,
+    /// how much of the colouring the histogram algorithms take from the histogram, between 0
+    /// and 1; the rest comes from the escape count, as with vanilla or smooth
+    #[arg(long, default_value_t = 1.)]
+    pub hist_blend: f64,
+    /// shade the image as a lit 3d surface, using normals from the orbit's derivative; only for
+    /// the mandelbrot and julia sets of z^d + c
+    #[arg(long, value_enum)]
+    pub lighting: Option<LightingModel>,
+    /// direction the light comes from, in degrees anticlockwise from the right of the image,
+    /// however the view is turned or mirrored
+    #[arg(long, default_value_t = 45., allow_negative_numbers = true)]
+    pub light_angle: f64,
+    /// height of the light above the image; higher lights cast softer shading
+    #[arg(long, default_value_t = 1.)]
+    pub light_height: f64,
+    /// number of samples averaged into each pixel to smooth out aliasing, which must be a
+    /// square number; each one costs as much as a whole render
+    #[arg(long, default_value_t = 1)]
+    pub samples: usize,
+    /// where in each pixel --samples are taken
+    #[arg(long, value_enum, default_value_t = SamplePattern::Grid)]
+    pub sample_pattern: SamplePattern,
+    /// only take --samples at pixels whose colour stands out from their neighbours' after a
+    /// first pass, which is much cheaper when most of the image is smooth; needs --samples of 4
+    /// or more, and not available with --deep-zoom
+    #[arg(long)]
+    pub adaptive: bool,
+    /// how far apart, as a distance between rgb colours, neighbouring pixels have to be for
+    /// --adaptive to re-sample them
+    #[arg(long, default_value_t = 32.)]
+    pub edge_threshold: f64,
+    /// also save the raw escape data to this file, so the render can be coloured again with
+    /// `recolor` without iterating. only the first of --samples is kept.
+    #[arg(long)]
+    pub raw_out: Option<String>,
+    /// don't save an image, only the data from --raw-out
+    #[arg(long, requires = "raw_out")]
+    pub no_image: bool,
+    /// print the view as a centre and zoom, a centre and radius, two corners and a pixel size,
+    /// for other programs, then stop without rendering. the corners are the box's before it's
+    /// turned or flipped, as `corners` takes them
+    #[arg(long)]
+    pub print_view: bool,
     #[command(subcommand)]
     pub command: Commands,
 }
 
 impl Cli {
-    pub fn get_hue_array(&self) -> Result<Vec<Vec<f64>>> {
-        let (width, height): (usize, usize) = self.resolution.to_dimensions();
+    /// the centre, zoom, julia constant and fractal the command asks for. the centre keeps
+    /// every digit it was given, for --deep-zoom's reference orbit.
+    fn get_view(&self) -> Result<(Point, f64, Option<Complex>, Fractal)> {
         let config: Configuration = confy::load("mandelbrot-rs", "config")?;
-        let (centre, zoom) = match &self.command {
-            &Commands::Centre { x, y, zoom } => (Complex::new(x, y), zoom as f64),
+        Ok(match &self.command {
+            Commands::Centre { x, y, zoom } => (
+                Point::new(x.clone(), y.clone()),
+                zoom.to_scale()?,
+                None,
+                self.fractal.unwrap_or_default(),
+            ),
+            Commands::CentreRadius { x, y, radius } => (
+                Point::new(x.clone(), y.clone()),
+                Zoom::from_radius(*radius)?.to_scale()?,
+                None,
+                self.fractal.unwrap_or_default(),
+            ),
+            Commands::CentrePixelSize { x, y, pixel_size } => {
+                let (width, height) = self.get_dimensions()?;
+                (
+                    Point::new(x.clone(), y.clone()),
+                    Zoom::from_pixel_size(*pixel_size, width, height)?.to_scale()?,
+                    None,
+                    self.fractal.unwrap_or_default(),
+                )
+            }
+            &Commands::Corners { x1, y1, x2, y2 } => {
+                let (width, height) = self.get_dimensions()?;
+                let (centre, zoom) =
+                    fit_corners(Complex::new(x1, y1), Complex::new(x2, y2), width, height)?;
+                (
+                    Point::from(centre),
+                    zoom.to_scale()?,
+                    None,
+                    self.fractal.unwrap_or_default(),
+                )
+            }
             Commands::CentreString { name } => {
                 let centre = config.get_named_point(name)?;
-                (centre.point, centre.zoom as f64)
+                (
+                    centre.point.clone(),
+                    centre.zoom.to_scale()?,
+                    None,
+                    self.fractal.unwrap_or(centre.fractal),
+                )
+            }
+            Commands::Julia {
+                c_re,
+                c_im,
+                x,
+                y,
+                zoom,
+            } => (
+                Point::new(x.clone(), y.clone()),
+                zoom.to_scale()?,
+                Some(Complex::new(*c_re, *c_im)),
+                self.fractal.unwrap_or_default(),
+            ),
+            Commands::Newton { .. } => {
+                return Err(anyhow!(
+                    "newton fractals are coloured by root, not escape count"
+                ))
+            }
+            Commands::Buddhabrot { .. } => {
+                return Err(anyhow!(
+                    "buddhabrots are coloured by orbit density, not escape count"
+                ))
             }
+            Commands::Recolor { .. } => {
+                return Err(anyhow!("recolor takes its view from the raw data"))
+            }
+        })
+    }
+
+    /// the size of the image in pixels: --width and --height, with whichever is missing scaled
+    /// to --resolution's aspect ratio
+    pub fn get_dimensions(&self) -> Result<(usize, usize)> {
+        let (preset_width, preset_height) = self.resolution.to_dimensions();
+        let scale = |length: usize, from: usize, to: usize| (length * to + from / 2) / from;
+        let (width, height) = match (self.width, self.height) {
+            (Some(width), Some(height)) => (width, height),
+            (Some(width), None) => (width, scale(width, preset_width, preset_height)),
+            (None, Some(height)) => (scale(height, preset_height, preset_width), height),
+            (None, None) => (preset_width, preset_height),
         };
-        let (x_range, y_range) = get_intervals(centre, zoom);
+        if width == 0 || height == 0 {
+            return Err(anyhow!("the image must be at least a pixel wide and high"));
+        }
+        Ok((width, height))
+    }
 
-        let post_fn: Box<dyn Fn(usize, Complex) -> f64 + std::marker::Sync> = match self.algorithm {
-            // for non-smooth, return identity
-            PlottingAlgorithm::Histogram | PlottingAlgorithm::Vanilla => {
-                Box::new(|escape_count, _| escape_count as f64)
+    /// which way up to draw the view: --rotation, or else the named point's, and the flips
+    fn get_orientation(&self) -> Result<Orientation> {
+        let rotation = match (self.rotation, &self.command) {
+            (Some(rotation), _) => rotation,
+            (None, Commands::CentreString { name }) => {
+                let config: Configuration = confy::load("mandelbrot-rs", "config")?;
+                config.get_named_point(name)?.rotation
             }
-            // for smooth, do some cool maths shit
-            PlottingAlgorithm::Smooth | PlottingAlgorithm::SmoothHistogram => {
-                Box::new(|escape_count, escape_val| {
-                    if escape_count < self.max_iters {
-                        let nu = (escape_val.abs_value_sq().ln() / 2.).log2();
-                        (escape_count + 1) as f64 - nu
-                    } else {
-                        self.max_iters as f64
-                    }
-                })
+            (None, _) => 0.,
+        };
+        Ok(Orientation {
+            rotation,
+            flip_horizontal: self.flip_horizontal,
+            flip_vertical: self.flip_vertical,
+        })
+    }
+
+    /// the map from the pixels of a `width` by `height` view at `zoom` to offsets from its
+    /// centre, with every pixel sampled `shift` pixels away from its corner
+    fn get_pixel_map(
+        &self,
+        zoom: f64,
+        shift: Complex,
+        width: usize,
+        height: usize,
+    ) -> Result<PixelMap> {
+        let pixel_map = PixelMap::new(zoom, self.get_orientation()?, width, height);
+        Ok(pixel_map.shifted(shift.re, shift.im))
+    }
+
+    /// the transform each point is moved by before it's iterated
+    fn get_transform(&self) -> Result<Transform> {
+        match self.transform {
+            TransformKind::Mobius if self.mobius.len() != 4 => Err(anyhow!(
+                "--transform mobius needs four coefficients from --mobius, e.g. --mobius 0,1,1,0"
+            )),
+            TransformKind::Mobius => Transform::mobius(&self.mobius),
+            kind => Ok(Transform {
+                kind,
+                tilt: self.sphere_tilt,
+                ..Transform::default()
+            }),
+        }
+    }
+
+    /// the view in each of the forms `Commands` takes it, one per line
+    pub fn describe_view(&self) -> Result<String> {
+        let (centre, zoom, julia) = match self.command {
+            Commands::Newton { x, y, zoom, .. } | Commands::Buddhabrot { x, y, zoom, .. } => {
+                (Point::from(Complex::new(x, y)), zoom, None)
+            }
+            _ => {
+                let (centre, zoom, julia, _) = self.get_view()?;
+                (centre, Zoom::new(zoom), julia)
             }
         };
+        let (width, height) = self.get_dimensions()?;
+        let orientation = self.get_orientation()?;
+        // the box `corners` fits, which is turned and flipped about its centre afterwards
+        let pixel_map = PixelMap::new(zoom.to_scale()?, Orientation::default(), width, height);
+        let top_left = centre.to_complex() + pixel_map.offset(0., 0.);
+        let bottom_right = centre.to_complex() + pixel_map.offset(width as f64, height as f64);
+        let mut lines = vec![
+            format!("centre: {}", centre),
+            format!("zoom: {} (2^{})", zoom, zoom.log2()),
+            format!("radius: {}", zoom.radius()),
+            format!(
+                "corners: {} {} {} {}",
+                top_left.re, top_left.im, bottom_right.re, bottom_right.im
+            ),
+            format!(
+                "pixel size: {} at {}x{}",
+                zoom.pixel_size(width, height),
+                width,
+                height
+            ),
+            format!("rotation: {}°", orientation.rotation),
+        ];
+        let flips = [
+            (orientation.flip_horizontal, "horizontally"),
+            (orientation.flip_vertical, "vertically"),
+        ];
+        let flips: Vec<_> = flips
+            .iter()
+            .filter(|(on, _)| *on)
+            .map(|(_, how)| *how)
+            .collect();
+        if !flips.is_empty() {
+            lines.push(format!("flipped: {}", flips.join(" and ")));
+        }
+        if let Some(c) = julia {
+            lines.push(format!("julia c: {} {}", c.re, c.im));
+        }
+        let transform = self.get_transform()?;
+        match transform.kind {
+            TransformKind::None => {}
+            TransformKind::Invert => lines.push("transform: invert".to_string()),
+            TransformKind::Mobius => {
+                let coefficients: Vec<_> = transform
+                    .coefficients
+                    .iter()
+                    .map(|k| format!("{}{:+}i", k.re, k.im))
+                    .collect();
+                lines.push(format!("transform: mobius {}", coefficients.join(",")));
+            }
+            TransformKind::Stereographic => lines.push(format!(
+                "transform: stereographic, tilted {}°",
+                transform.tilt
+            )),
+        }
+        Ok(lines.join("\n"))
+    }
 
-        let escape_counts = generate_escape_counts(
-            &x_range,
-            &y_range,
-            width,
-            height,
-            self.max_iters,
-            self.bailout,
-            post_fn,
-        );
+    pub fn get_hue_array(&self) -> Result<Field<f64>> {
+        Ok(self
+            .get_shifted_hue_array(Complex::id(), &Renderer::new())?
+            .0)
+    }
 
-        Ok(match self.algorithm {
+    /// `get_hue_array` with every pixel sampled `shift` pixels away from its corner, along with
+    /// how brightly --lighting lights each pixel, if it's on
+    fn get_shifted_hue_array(
+        &self,
+        shift: Complex,
+        renderer: &Renderer,
+    ) -> Result<(Field<f64>, Option<Field<f64>>)> {
+        let histogram_options = self.get_histogram_options()?;
+        let (escape_counts, light_array) = self.get_values(shift, renderer)?;
+        let hue_array = match self.algorithm {
             PlottingAlgorithm::Vanilla | PlottingAlgorithm::Smooth => {
                 normalise_escape_counts(&escape_counts, self.max_iters)
             }
             PlottingAlgorithm::Histogram | PlottingAlgorithm::SmoothHistogram => {
-                generate_hist_counts(&escape_counts, self.max_iters, width * height)
+                generate_hist_counts(&escape_counts, self.max_iters, histogram_options)
+            }
+            // these are already hues
+            PlottingAlgorithm::StripeAverage
+            | PlottingAlgorithm::TriangleInequalityAverage
+            | PlottingAlgorithm::DistanceEstimate
+            | PlottingAlgorithm::OrbitTrap => escape_counts,
+        };
+        Ok((hue_array, light_array))
+    }
+
+    /// how --algorithm histogram and smooth-histogram spread the palette
+    fn get_histogram_options(&self) -> Result<HistogramOptions> {
+        if !(0. ..=1.).contains(&self.hist_blend) {
+            return Err(anyhow!("--hist-blend must be between 0 and 1"));
+        }
+        Ok(HistogramOptions {
+            equalisation: self.hist_equalisation,
+            exclude_interior: self.hist_exclude_interior,
+            blend: self.hist_blend,
+        })
+    }
+
+    /// turns one of `get_values`' values into a hue, as `get_shifted_hue_array` does, with
+    /// histogram colouring spread by `histogram`
+    fn value_to_hue(&self, value: f64, histogram: Option<&Histogram>) -> f64 {
+        match self.algorithm {
+            PlottingAlgorithm::Vanilla | PlottingAlgorithm::Smooth => value / self.max_iters as f64,
+            PlottingAlgorithm::Histogram | PlottingAlgorithm::SmoothHistogram => histogram
+                .expect("histogram colouring needs a histogram")
+                .hue(value),
+            _ => value,
+        }
+    }
+
+    /// a rough hue for one of the values the renderer's `on_tile` callback is given, for
+    /// showing a render as it comes in. histograms need the whole image, so until then
+    /// histogram colouring is stood in for by escape counts on a log scale.
+    pub fn preview_hue(&self, value: f64) -> f64 {
+        match self.algorithm {
+            PlottingAlgorithm::Histogram | PlottingAlgorithm::SmoothHistogram => {
+                value.ln_1p() / (self.max_iters as f64).ln_1p()
+            }
+            _ => self.value_to_hue(value, None),
+        }
+    }
+
+    /// the orbit average --algorithm colours by, if any
+    fn get_average(&self) -> Option<OrbitAverage> {
+        match self.algorithm {
+            PlottingAlgorithm::StripeAverage => Some(OrbitAverage::Stripe {
+                density: self.stripe_density,
+            }),
+            PlottingAlgorithm::TriangleInequalityAverage => Some(OrbitAverage::TriangleInequality),
+            _ => None,
+        }
+    }
+
+    /// the escape count of `escape` with a fractional part that smooths out the bands between
+    /// counts, or `max_iters` for points in the set
+    fn smooth_iters(&self, escape: Escape, exponent: Exponent) -> f64 {
+        match exponent.smoothing_degree() {
+            Some(degree) if escape.iters < self.max_iters => {
+                let nu = (escape.z.abs_value_sq().ln() / 2.).log(degree);
+                ((escape.iters + 1) as f64 - nu).max(0.)
+            }
+            Some(_) => self.max_iters as f64,
+            // orbits which don't escape by powering have no smooth correction
+            None => escape.iters as f64,
+        }
+    }
+
+    /// the orbit average of `escape`, which is already between 0 and 1, with points in the set
+    /// at 1
+    fn average_hue(&self, escape: Escape, exponent: Exponent) -> f64 {
+        if escape.iters < self.max_iters {
+            escape.smooth_average(self.bailout, exponent).unwrap_or(0.)
+        } else {
+            1.
+        }
+    }
+
+    /// what each pixel's `Escape` is boiled down to before it's turned into a hue
+    fn get_post_fn(&self, exponent: Exponent) -> Box<dyn Fn(Escape) -> f64 + Sync + '_> {
+        match self.algorithm {
+            // for non-smooth, return identity
+            PlottingAlgorithm::Histogram | PlottingAlgorithm::Vanilla => {
+                Box::new(|escape| escape.iters as f64)
+            }
+            // for smooth, do some cool maths shit
+            PlottingAlgorithm::Smooth | PlottingAlgorithm::SmoothHistogram => {
+                Box::new(move |escape| self.smooth_iters(escape, exponent))
             }
+            PlottingAlgorithm::StripeAverage | PlottingAlgorithm::TriangleInequalityAverage => {
+                Box::new(move |escape| self.average_hue(escape, exponent))
+            }
+            PlottingAlgorithm::DistanceEstimate | PlottingAlgorithm::OrbitTrap => {
+                unreachable!("coloured without escape counts")
+            }
+        }
+    }
+
+    /// how brightly `lighting` lights `escape`, or 1 without any
+    fn brightness(&self, lighting: Option<Lighting>, escape: Escape) -> f64 {
+        lighting.map_or(1., |lighting| {
+            lighting.escape_brightness(escape, self.max_iters)
         })
     }
 
+    /// the value each pixel is coloured by: escape counts, or their smooth versions, for
+    /// `Vanilla`, `Smooth` and the histograms, and hues for everything else. with --lighting,
+    /// each pixel's brightness comes out of the same pass.
+    fn get_values(
+        &self,
+        shift: Complex,
+        renderer: &Renderer,
+    ) -> Result<(Field<f64>, Option<Field<f64>>)> {
+        let (centre, zoom, julia, fractal) = self.get_view()?;
+        let centre = centre.to_complex();
+        let exponent = Exponent::from(self.exponent);
+        let lighting = self.get_lighting(fractal, exponent)?;
+        // distances aren't escape counts, so they're worked out on their own
+        let shaded = match self.algorithm {
+            PlottingAlgorithm::DistanceEstimate => self.get_distance_hues(
+                centre, zoom, shift, julia, fractal, exponent, lighting, renderer,
+            )?,
+            PlottingAlgorithm::OrbitTrap => self.get_trap_hues(
+                centre, zoom, shift, julia, fractal, exponent, lighting, renderer,
+            )?,
+            _ => {
+                let post_fn = self.get_post_fn(exponent);
+                self.get_escapes(
+                    shift,
+                    renderer,
+                    lighting.is_some(),
+                    |escape| (post_fn(escape), self.brightness(lighting, escape)),
+                    |&(value, _)| value,
+                )?
+            }
+        };
+        let light_array = lighting.map(|_| shaded.map(|&(_, brightness)| brightness));
+        Ok((shaded.map(|&(value, _)| value), light_array))
+    }
+
+    /// iterates every pixel of the view, moved by `shift` pixels, and boils each pixel's
+    /// `Escape` down with `post_fn`, tracking the derivative in it with `derivative`.
+    /// `to_value` picks what of each result is shown by the renderer's `on_tile` callback.
+    fn get_escapes<R, F, V>(
+        &self,
+        shift: Complex,
+        renderer: &Renderer,
+        derivative: bool,
+        post_fn: F,
+        to_value: V,
+    ) -> Result<Field<R>>
+    where
+        R: Copy + PartialEq + Send + Sync,
+        F: Fn(Escape) -> R + Sync,
+        V: Fn(&R) -> f64 + Sync,
+    {
+        let (width, height) = self.get_dimensions()?;
+        let (centre, zoom, julia, fractal) = self.get_view()?;
+        let exponent = Exponent::from(self.exponent);
+        let average = self.get_average();
+        let transform = self.get_transform()?;
+        let pixel_map = self.get_pixel_map(zoom, shift, width, height)?;
+        let escape_counts = if self.deep_zoom {
+            if average.is_some() {
+                return Err(anyhow!("orbit averages can't be used with --deep-zoom"));
+            }
+            if !transform.is_identity() {
+                return Err(anyhow!("transforms can't be used with --deep-zoom"));
+            }
+            let degree = match exponent {
+                Exponent::Integer(n) if n >= 2 => n as u32,
+                _ => return Err(anyhow!("deep zoom needs an integer exponent of at least 2")),
+            };
+            generate_perturbed_escape_counts(
+                &centre,
+                julia,
+                fractal,
+                degree,
+                &pixel_map,
+                width,
+                height,
+                self.max_iters,
+                self.bailout,
+                !self.no_interior_checks,
+                derivative,
+                renderer,
+                &post_fn,
+            )?
+        } else {
+            let centre = centre.to_complex();
+            macro_rules! generate {
+                ($real:ty) => {
+                    renderer.render_mapped(
+                        width,
+                        height,
+                        true,
+                        |tile| {
+                            let pixel_map = pixel_map.tile(tile);
+                            if self.subdivide {
+                                generate_subdivided_escape_counts::<$real, _, _>(
+                                    centre,
+                                    julia,
+                                    &transform,
+                                    fractal,
+                                    exponent,
+                                    &pixel_map,
+                                    tile.width,
+                                    tile.height,
+                                    self.max_iters,
+                                    self.bailout,
+                                    !self.no_interior_checks,
+                                    average,
+                                    derivative,
+                                    &post_fn,
+                                )
+                            } else {
+                                generate_escape_counts::<$real, _, _>(
+                                    centre,
+                                    julia,
+                                    &transform,
+                                    fractal,
+                                    exponent,
+                                    &pixel_map,
+                                    tile.width,
+                                    tile.height,
+                                    self.max_iters,
+                                    self.bailout,
+                                    !self.no_interior_checks,
+                                    average,
+                                    derivative,
+                                    &post_fn,
+                                )
+                            }
+                        },
+                        &to_value,
+                    )?
+                };
+            }
+            match self.precision.resolve(centre, &pixel_map, width, height) {
+                Precision::Single => generate!(f32),
+                Precision::Double => generate!(f64),
+                Precision::DoubleDouble => generate!(DoubleDouble),
+                Precision::Auto => unreachable!("resolve never returns Auto"),
+            }
+        };
+        Ok(escape_counts)
+    }
+
+    /// `get_values` for points `offsets` away from the centre of the view rather than a grid of
+    /// pixels, for re-sampling a few of them. `pixel_spacing` is the width of a pixel, which
+    /// distance estimates are measured in. not available with --deep-zoom.
+    fn get_point_values(
+        &self,
+        offsets: &[Complex],
+        pixel_spacing: f64,
+    ) -> Result<(Vec<f64>, Option<Vec<f64>>)> {
+        if self.deep_zoom {
+            return Err(anyhow!(
+                "points can't be sampled on their own with --deep-zoom"
+            ));
+        }
+        let (centre, zoom, julia, fractal) = self.get_view()?;
+        let centre = centre.to_complex();
+        let exponent = Exponent::from(self.exponent);
+        let lighting = self.get_lighting(fractal, exponent)?;
+        let (width, height) = self.get_dimensions()?;
+        let pixel_map = self.get_pixel_map(zoom, Complex::id(), width, height)?;
+        let interior_checks = !self.no_interior_checks;
+        let transform = self.get_transform()?;
+        macro_rules! generate {
+            ($real:ty) => {
+                match self.algorithm {
+                    PlottingAlgorithm::DistanceEstimate => map_points(
+                        centre,
+                        julia,
+                        &Transform::default(),
+                        offsets,
+                        |c: Complex<$real>, z_0| {
+                            let escape = c
+                                .escape_count_with_derivative(
+                                    z_0,
+                                    julia.is_some(),
+                                    exponent,
+                                    self.bailout,
+                                    self.max_iters,
+                                    interior_checks,
+                                )
+                                .to_f64();
+                            let distance = escape.distance_estimate(self.max_iters) / pixel_spacing;
+                            (
+                                (self.thickness / distance).min(1.),
+                                self.brightness(lighting, escape),
+                            )
+                        },
+                    ),
+                    PlottingAlgorithm::OrbitTrap => {
+                        let trap = self.get_trap()?;
+                        map_points(
+                            centre,
+                            julia,
+                            &transform,
+                            offsets,
+                            |c: Complex<$real>, z_0| {
+                                let (distance, escape) = trap.closest_approach(
+                                    c,
+                                    z_0,
+                                    julia.is_some(),
+                                    fractal,
+                                    exponent,
+                                    self.bailout,
+                                    self.max_iters,
+                                    interior_checks,
+                                    lighting.is_some(),
+                                );
+                                (
+                                    distance.map_or(1., |distance| trap.hue(distance)),
+                                    self.brightness(lighting, escape.to_f64()),
+                                )
+                            },
+                        )
+                    }
+                    _ => {
+                        let post_fn = self.get_post_fn(exponent);
+                        generate_point_escape_counts::<$real, _, _>(
+                            centre,
+                            julia,
+                            &transform,
+                            fractal,
+                            exponent,
+                            offsets,
+                            self.max_iters,
+                            self.bailout,
+                            interior_checks,
+                            self.get_average(),
+                            lighting.is_some(),
+                            |escape| (post_fn(escape), self.brightness(lighting, escape)),
+                        )
+                    }
+                }
+            };
+        }
+        let shaded: Vec<(f64, f64)> =
+            match self.precision.resolve(centre, &pixel_map, width, height) {
+                Precision::Single => generate!(f32),
+                Precision::Double => generate!(f64),
+                Precision::DoubleDouble => generate!(DoubleDouble),
+                Precision::Auto => unreachable!("resolve never returns Auto"),
+            };
+        let brightness =
+            lighting.map(|_| shaded.iter().map(|&(_, brightness)| brightness).collect());
+        Ok((
+            shaded.into_iter().map(|(value, _)| value).collect(),
+            brightness,
+        ))
+    }
+
+    /// hues from the estimated distance to the boundary: 1 within `thickness` pixels of the
+    /// set and inside it, falling off with distance outside, each with its brightness under
+    /// `lighting`
+    #[allow(clippy::too_many_arguments)]
+    fn get_distance_hues(
+        &self,
+        centre: Complex,
+        zoom: f64,
+        shift: Complex,
+        julia: Option<Complex>,
+        fractal: Fractal,
+        exponent: Exponent,
+        lighting: Option<Lighting>,
+        renderer: &Renderer,
+    ) -> Result<Field<(f64, f64)>> {
+        if fractal != Fractal::Mandelbrot {
+            return Err(anyhow!(
+                "distance estimation only works for the mandelbrot and julia sets of z^d + c"
+            ));
+        }
+        if exponent.smoothing_degree().is_none() {
+            return Err(anyhow!(
+                "distance estimation needs an exponent greater than 1"
+            ));
+        }
+        if self.deep_zoom {
+            return Err(anyhow!(
+                "distance estimation can't be used with --deep-zoom"
+            ));
+        }
+        if !self.get_transform()?.is_identity() {
+            return Err(anyhow!(
+                "distances can't be estimated through a transform, which stretches them unevenly"
+            ));
+        }
+        let (width, height) = self.get_dimensions()?;
+        let pixel_map = self.get_pixel_map(zoom, shift, width, height)?;
+        macro_rules! generate {
+            ($real:ty) => {
+                renderer.render_mapped(
+                    width,
+                    height,
+                    true,
+                    |tile| {
+                        let pixel_map = pixel_map.tile(tile);
+                        generate_distance_estimates::<$real, _, _>(
+                            centre,
+                            julia,
+                            exponent,
+                            &pixel_map,
+                            tile.width,
+                            tile.height,
+                            self.max_iters,
+                            self.bailout,
+                            !self.no_interior_checks,
+                            |distance, escape| {
+                                (
+                                    (self.thickness / distance).min(1.),
+                                    self.brightness(lighting, escape),
+                                )
+                            },
+                        )
+                    },
+                    |&(hue, _)| hue,
+                )?
+            };
+        }
+        Ok(
+            match self.precision.resolve(centre, &pixel_map, width, height) {
+                Precision::Single => generate!(f32),
+                Precision::Double => generate!(f64),
+                Precision::DoubleDouble => generate!(DoubleDouble),
+                Precision::Auto => unreachable!("resolve never returns Auto"),
+            },
+        )
+    }
+
+    fn get_trap(&self) -> Result<OrbitTrap> {
+        // hues are distances relative to the size, so they'd go negative or undefined otherwise
+        if self.trap_size.is_nan() || self.trap_size <= 0. {
+            return Err(anyhow!(
+                "--trap-size must be positive, not {}",
+                self.trap_size
+            ));
+        }
+        Ok(OrbitTrap {
+            shape: self.trap,
+            centre: self.trap_centre,
+            size: self.trap_size,
+        })
+    }
+
+    /// hues from how close each orbit comes to the orbit trap, each with its brightness under
+    /// `lighting`; points in the set get 1
+    #[allow(clippy::too_many_arguments)]
+    fn get_trap_hues(
+        &self,
+        centre: Complex,
+        zoom: f64,
+        shift: Complex,
+        julia: Option<Complex>,
+        fractal: Fractal,
+        exponent: Exponent,
+        lighting: Option<Lighting>,
+        renderer: &Renderer,
+    ) -> Result<Field<(f64, f64)>> {
+        if self.deep_zoom {
+            return Err(anyhow!("orbit traps can't be used with --deep-zoom"));
+        }
+        let trap = self.get_trap()?;
+        let transform = self.get_transform()?;
+        let (width, height) = self.get_dimensions()?;
+        let pixel_map = self.get_pixel_map(zoom, shift, width, height)?;
+        macro_rules! generate {
+            ($real:ty) => {
+                renderer.render_mapped(
+                    width,
+                    height,
+                    true,
+                    |tile| {
+                        let pixel_map = pixel_map.tile(tile);
+                        generate_trap_distances::<$real, _, _>(
+                            centre,
+                            julia,
+                            &transform,
+                            fractal,
+                            exponent,
+                            &trap,
+                            &pixel_map,
+                            tile.width,
+                            tile.height,
+                            self.max_iters,
+                            self.bailout,
+                            !self.no_interior_checks,
+                            lighting.is_some(),
+                            |distance, escape| {
+                                (
+                                    distance.map_or(1., |distance| trap.hue(distance)),
+                                    self.brightness(lighting, escape),
+                                )
+                            },
+                        )
+                    },
+                    |&(hue, _)| hue,
+                )?
+            };
+        }
+        Ok(
+            match self.precision.resolve(centre, &pixel_map, width, height) {
+                Precision::Single => generate!(f32),
+                Precision::Double => generate!(f64),
+                Precision::DoubleDouble => generate!(DoubleDouble),
+                Precision::Auto => unreachable!("resolve never returns Auto"),
+            },
+        )
+    }
+
+    /// how brightly each pixel is lit by --lighting, or `None` without it
+    pub fn get_light_array(&self) -> Result<Option<Field<f64>>> {
+        Ok(self.get_values(Complex::id(), &Renderer::new())?.1)
+    }
+
+    /// the light set up by --lighting, if it's on
+    fn get_lighting(&self, fractal: Fractal, exponent: Exponent) -> Result<Option<Lighting>> {
+        let Some(model) = self.lighting else {
+            return Ok(None);
+        };
+        if fractal != Fractal::Mandelbrot {
+            return Err(anyhow!(
+                "lighting only works for the mandelbrot and julia sets of z^d + c"
+            ));
+        }
+        if exponent.smoothing_degree().is_none() {
+            return Err(anyhow!("lighting needs an exponent greater than 1"));
+        }
+        if !self.get_transform()?.is_identity() {
+            return Err(anyhow!(
+                "lighting can't be used with a transform, which would turn its normals"
+            ));
+        }
+        Ok(Some(Lighting {
+            model,
+            // the light is placed relative to the image, whichever way the view is drawn
+            angle: self.get_orientation()?.plane_angle(self.light_angle),
+            height: self.light_height,
+        }))
+    }
+
     pub fn get_palette(&self) -> Result<ColorPalette> {
         let config: Configuration = confy::load("mandelbrot-rs", "config")?;
         config
             .get_palette(&self.palette)
             .map(|p| p.clone().repeat(self.palette_repeats))
     }
+
+    /// renders the image for any command, colouring escape counts with the chosen palette,
+    /// along with how many pixels --adaptive re-sampled
+    pub fn get_image(&self) -> Result<(RgbImage, usize)> {
+        self.render(&Renderer::new())
+    }
+
+    /// `get_image`, with progress reported to and cancellation checked by `renderer`.
+    /// buddhabrots aren't split into tiles, so they report no progress and can't be cancelled.
+    pub fn render(&self, renderer: &Renderer) -> Result<(RgbImage, usize)> {
+        if matches!(
+            self.command,
+            Commands::Newton { .. } | Commands::Buddhabrot { .. }
+        ) && !self.get_transform()?.is_identity()
+        {
+            return Err(anyhow!(
+                "transforms only work for escape-time fractals, not newton or buddhabrot"
+            ));
+        }
+        match self.command {
+            Commands::Newton { .. } => return Ok((self.get_newton_image(renderer)?, 0)),
+            Commands::Buddhabrot { .. } => return Ok((self.get_buddhabrot_image()?, 0)),
+            Commands::Recolor { ref input } => {
+                return Ok((self.colour_raw_data(&RawData::load(input)?)?, 0))
+            }
+            _ => {}
+        }
+        self.get_image_with_palette(&self.get_palette()?, renderer)
+    }
+
+    /// the raw data --raw-out saves: escape counts, smooth escape counts and final iterates
+    /// for the view, plus the orbit average for --algorithm stripe-average and
+    /// triangle-inequality-average, and the brightness with --lighting
+    pub fn get_raw_data(&self, renderer: &Renderer) -> Result<RawData> {
+        let (centre, zoom, julia, fractal) = self.get_view()?;
+        let exponent = Exponent::from(self.exponent);
+        let lighting = self.get_lighting(fractal, exponent)?;
+        let (width, height) = self.get_dimensions()?;
+        renderer.begin(width * height);
+
+        let escapes = self.get_escapes(
+            Complex::id(),
+            renderer,
+            lighting.is_some(),
+            |escape| RawEscape {
+                iters: escape.iters as f64,
+                smooth: self.smooth_iters(escape, exponent),
+                z_re: escape.z.re,
+                z_im: escape.z.im,
+                average: self.average_hue(escape, exponent),
+                light: self.brightness(lighting, escape),
+            },
+            |escape| escape.iters,
+        )?;
+        let mut raw = RawData::new(
+            RawParams {
+                fractal,
+                exponent: self.exponent,
+                centre,
+                zoom: Zoom::new(zoom),
+                orientation: self.get_orientation()?,
+                transform: self.get_transform()?,
+                julia,
+                max_iters: self.max_iters,
+                bailout: self.bailout,
+                deep_zoom: self.deep_zoom,
+            },
+            width,
+            height,
+        );
+        raw.push(raw::ITERS, escapes.map(|escape| escape.iters));
+        raw.push(raw::SMOOTH, escapes.map(|escape| escape.smooth));
+        raw.push(raw::Z_RE, escapes.map(|escape| escape.z_re));
+        raw.push(raw::Z_IM, escapes.map(|escape| escape.z_im));
+        match self.algorithm {
+            PlottingAlgorithm::StripeAverage => {
+                raw.push(raw::STRIPE_AVERAGE, escapes.map(|escape| escape.average))
+            }
+            PlottingAlgorithm::TriangleInequalityAverage => raw.push(
+                raw::TRIANGLE_INEQUALITY_AVERAGE,
+                escapes.map(|escape| escape.average),
+            ),
+            _ => {}
+        }
+        if lighting.is_some() {
+            raw.push(raw::LIGHT, escapes.map(|escape| escape.light));
+        }
+        Ok(raw)
+    }
+
+    /// whether the image can be coloured from --raw-out's data instead of rendering it again
+    pub fn colours_from_raw_data(&self) -> bool {
+        self.samples == 1
+            && !self.adaptive
+            && !matches!(
+                self.algorithm,
+                PlottingAlgorithm::DistanceEstimate | PlottingAlgorithm::OrbitTrap
+            )
+    }
+
+    /// colours raw data with --palette, --palette-repeats and --algorithm without iterating
+    /// again, normalising by the iteration limit it was rendered with. --lighting shades it
+    /// with the brightness saved alongside, so the light can't be moved.
+    pub fn colour_raw_data(&self, raw: &RawData) -> Result<RgbImage> {
+        let max_iters = raw.params.max_iters;
+        let channel = |name: &str| {
+            raw.channel(name)
+                .ok_or_else(|| anyhow!("the raw data has no {} channel", name))
+        };
+        let hue_array = match self.algorithm {
+            PlottingAlgorithm::Vanilla => normalise_escape_counts(channel(raw::ITERS)?, max_iters),
+            PlottingAlgorithm::Smooth => normalise_escape_counts(channel(raw::SMOOTH)?, max_iters),
+            PlottingAlgorithm::Histogram => generate_hist_counts(
+                channel(raw::ITERS)?,
+                max_iters,
+                self.get_histogram_options()?,
+            ),
+            PlottingAlgorithm::SmoothHistogram => generate_hist_counts(
+                channel(raw::SMOOTH)?,
+                max_iters,
+                self.get_histogram_options()?,
+            ),
+            PlottingAlgorithm::StripeAverage => channel(raw::STRIPE_AVERAGE)?.clone(),
+            PlottingAlgorithm::TriangleInequalityAverage => {
+                channel(raw::TRIANGLE_INEQUALITY_AVERAGE)?.clone()
+            }
+            PlottingAlgorithm::DistanceEstimate | PlottingAlgorithm::OrbitTrap => {
+                return Err(anyhow!(
+                    "raw data doesn't keep the distances {:?} colours by",
+                    self.algorithm
+                ))
+            }
+        };
+        let light_array = match self.lighting {
+            Some(_) => Some(channel(raw::LIGHT)?),
+            None => None,
+        };
+        let palette = self.get_palette()?;
+        Ok(Field::from_fn(raw.width(), raw.height(), |x, y| {
+            let colour = palette.value(hue_array[(x, y)]);
+            match light_array {
+                Some(light_array) => lighting::apply(colour, light_array[(x, y)]),
+                None => colour,
+            }
+        })
+        .to_image(|&colour| colour))
+    }
+
+    /// renders an escape-time image coloured with `palette`. with more than one of --samples,
+    /// the image is rendered once per sample with the view shifted by less than a pixel, and the
+    /// colours averaged, so histogram colouring and lighting are applied before blending.
+    pub fn get_image_with_palette(
+        &self,
+        palette: &ColorPalette,
+        renderer: &Renderer,
+    ) -> Result<(RgbImage, usize)> {
+        let (width, height) = self.get_dimensions()?;
+        let shifts = self.sample_pattern.shifts(self.samples)?;
+        if self.adaptive && shifts.len() == 1 {
+            // a single sample would just take the first pass's again, or a noisier one
+            return Err(anyhow!(
+                "--adaptive needs more than one of --samples to re-sample edge pixels with"
+            ));
+        }
+        if self.adaptive && self.deep_zoom {
+            // caught here rather than once the whole first pass has been rendered
+            return Err(anyhow!(
+                "--adaptive can't re-sample single pixels with --deep-zoom"
+            ));
+        }
+        // a pass per sample, or just the first with --adaptive
+        let passes = if self.adaptive { 1 } else { shifts.len() };
+        renderer.begin(passes * width * height);
+        if self.adaptive {
+            return self.get_adaptive_image(palette, renderer);
+        }
+        let mut sums = Field::new(width, height, vec![[0.; 3]; width * height]);
+        for &shift in &shifts {
+            let (hue_array, light_array) = self.get_shifted_hue_array(shift, renderer)?;
+            sums.values_mut()
+                .par_iter_mut()
+                .zip(hue_array.values())
+                .enumerate()
+                .for_each(|(i, (sum, &hue))| {
+                    let mut colour = palette.value(hue);
+                    if let Some(light_array) = &light_array {
+                        colour = lighting::apply(colour, light_array.values()[i]);
+                    }
+                    for (sum, channel) in sum.iter_mut().zip(colour.0) {
+                        *sum += channel as f64;
+                    }
+                });
+        }
+        let image = sums
+            .to_image(|sum| Rgb(sum.map(|channel| (channel / shifts.len() as f64).round() as u8)));
+        Ok((image, 0))
+    }
+
+    /// renders with a single sample per pixel, then re-samples just the pixels on edges, as
+    /// found by `find_edges`, with --samples each. histogram colouring keeps the first pass's
+    /// histogram, so re-sampled pixels are coloured on the same scale as their neighbours.
+    fn get_adaptive_image(
+        &self,
+        palette: &ColorPalette,
+        renderer: &Renderer,
+    ) -> Result<(RgbImage, usize)> {
+        let (width, height) = self.get_dimensions()?;
+        let (_, zoom, _, _) = self.get_view()?;
+        let shifts = self.sample_pattern.shifts(self.samples)?;
+        let histogram_options = self.get_histogram_options()?;
+        let (values, light_array) = self.get_values(Complex::id(), renderer)?;
+        let histogram = matches!(
+            self.algorithm,
+            PlottingAlgorithm::Histogram | PlottingAlgorithm::SmoothHistogram
+        )
+        .then(|| Histogram::new(&values, self.max_iters, histogram_options));
+        let colour = |value: f64, brightness: Option<f64>| {
+            let colour = palette.value(self.value_to_hue(value, histogram.as_ref()));
+            match brightness {
+                Some(brightness) => lighting::apply(colour, brightness),
+                None => colour,
+            }
+        };
+        let mut image = Field::from_fn(width, height, |x, y| {
+            colour(values[(x, y)], light_array.as_ref().map(|l| l[(x, y)]))
+        })
+        .to_image(|&colour| colour);
+
+        renderer.check()?;
+        let edges = find_edges(&image, self.edge_threshold);
+        let pixel_map = self.get_pixel_map(zoom, Complex::id(), width, height)?;
+        let offsets: Vec<Complex> = edges
+            .iter()
+            .flat_map(|&(x, y)| {
+                shifts
+                    .iter()
+                    .map(move |shift| pixel_map.offset(x as f64 + shift.re, y as f64 + shift.im))
+            })
+            .collect();
+        let (values, brightness) = self.get_point_values(&offsets, pixel_map.spacing())?;
+        for (i, &(x, y)) in edges.iter().enumerate() {
+            let mut sum = [0.; 3];
+            for j in i * shifts.len()..(i + 1) * shifts.len() {
+                let colour = colour(values[j], brightness.as_ref().map(|b| b[j]));
+                for (sum, channel) in sum.iter_mut().zip(colour.0) {
+                    *sum += channel as f64;
+                }
+            }
+            let colour = sum.map(|channel| (channel / shifts.len() as f64).round() as u8);
+            image.put_pixel(x, y, Rgb(colour));
+        }
+        Ok((image, edges.len()))
+    }
+
+    fn get_newton_image(&self, renderer: &Renderer) -> Result<RgbImage> {
+        let Commands::Newton {
+            roots,
+            coefficients,
+            root_palettes,
+            x,
+            y,
+            zoom,
+        } = &self.command
+        else {
+            return Err(anyhow!("not a newton fractal"));
+        };
+        let (polynomial, roots) = if roots.is_empty() {
+            let polynomial = Polynomial::from_coefficients(coefficients)?;
+            let roots = polynomial.roots();
+            (polynomial, roots)
+        } else {
+            (Polynomial::from_roots(roots)?, Root::distinct(roots))
+        };
+
+        let (width, height) = self.get_dimensions()?;
+        let pixel_map = self.get_pixel_map(zoom.to_scale()?, Complex::id(), width, height)?;
+        renderer.begin(width * height);
+        let basins = renderer.render(width, height, true, |tile| {
+            let pixel_map = pixel_map.tile(tile);
+            generate_basins(
+                &polynomial,
+                &roots,
+                Complex::new(*x, *y),
+                &pixel_map,
+                tile.width,
+                tile.height,
+                self.max_iters,
+            )
+        })?;
+
+        let config: Configuration = confy::load("mandelbrot-rs", "config")?;
+        let palettes = root_palettes
+            .iter()
+            .map(|name| {
+                config
+                    .get_palette(name)
+                    .map(|p| p.clone().repeat(self.palette_repeats))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        // shade on a log scale, since most pixels converge within a handful of steps
+        let slowest = basins
+            .values()
+            .iter()
+            .flatten()
+            .map(|basin| basin.iters)
+            .max()
+            .unwrap_or(0);
+        let shade = |iters: usize| (iters as f64).ln_1p() / (slowest as f64).ln_1p().max(1.);
+
+        Ok(basins.to_image(|basin| match *basin {
+            None => Rgb([0, 0, 0]),
+            Some(basin) if palettes.is_empty() => hsv_to_rgb(
+                basin.root as f64 / roots.len() as f64,
+                0.8,
+                1. - 0.85 * shade(basin.iters),
+            ),
+            Some(basin) => palettes[basin.root % palettes.len()].value(shade(basin.iters)),
+        }))
+    }
+
+    fn get_buddhabrot_image(&self) -> Result<RgbImage> {
+        let &Commands::Buddhabrot {
+            samples,
+            seed,
+            ref channel_iters,
+            x,
+            y,
+            zoom,
+        } = &self.command
+        else {
+            return Err(anyhow!("not a buddhabrot"));
+        };
+        let channel_iters = match channel_iters.len() {
+            0 => vec![self.max_iters],
+            3 => channel_iters.clone(),
+            _ => {
+                return Err(anyhow!(
+                    "--channel-iters needs one limit for each of red, green and blue"
+                ))
+            }
+        };
+
+        let (width, height) = self.get_dimensions()?;
+        let pixel_map = self.get_pixel_map(zoom.to_scale()?, Complex::id(), width, height)?;
+        let densities = generate_densities(
+            self.fractal.unwrap_or_default(),
+            Exponent::from(self.exponent),
+            Complex::new(x, y),
+            &pixel_map,
+            width,
+            height,
+            &channel_iters,
+            self.bailout,
+            samples,
+            seed,
+        );
+        let channels: Vec<_> = densities.iter().map(normalise_densities).collect();
+
+        if let [red, green, blue] = &channels[..] {
+            let level = |channel: &Field<f64>, x: u32, y: u32| {
+                (channel[(x as usize, y as usize)] * 255.).round() as u8
+            };
+            return Ok(ImageBuffer::from_fn(width as u32, height as u32, |x, y| {
+                Rgb([level(red, x, y), level(green, x, y), level(blue, x, y)])
+            }));
+        }
+        let palette = self.get_palette()?;
+        Ok(channels[0].to_image(|&density| palette.value(density)))
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
@@ -104,6 +1331,16 @@ pub enum Resolution {
     Low,
     Med,
     High,
+    /// 4k
+    #[value(alias = "4k")]
+    Ultra,
+    /// 8k, for prints
+    #[value(name = "8k")]
+    EightK,
+    /// 1080 by 1080, for social media posts
+    Square,
+    /// 1080 by 1920 portrait, for phone wallpapers
+    Phone,
 }
 
 impl Resolution {
@@ -112,30 +1349,216 @@ impl Resolution {
             Resolution::Low => (320, 180),
             Resolution::Med => (960, 540),
             Resolution::High => (1920, 1080),
+            Resolution::Ultra => (3840, 2160),
+            Resolution::EightK => (7680, 4320),
+            Resolution::Square => (1080, 1080),
+            Resolution::Phone => (1080, 1920),
         }
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum Precision {
+    /// f64, or double-double once f64 can't resolve the view
+    Auto,
+    /// f32, only good for quick previews, so it's never picked by `Auto`
+    Single,
+    /// f64
+    Double,
+    /// double-double, good for roughly 106 bits
+    DoubleDouble,
+}
+
+// bits of precision kept on top of what's needed to tell neighbouring pixels apart,
+// to absorb the rounding error which builds up over the orbit
+const PRECISION_GUARD_BITS: u32 = 16;
+
+impl Precision {
+    /// resolves `Auto` to a concrete number type, based on how many bits are needed to tell
+    /// neighbouring pixels of a `width` by `height` image apart, when `pixel_map` is around
+    /// `centre`. `Auto` never goes below f64, which also covers transformed points, as they
+    /// aren't spaced like the pixels
+    pub fn resolve(
+        self,
+        centre: Complex,
+        pixel_map: &PixelMap,
+        width: usize,
+        height: usize,
+    ) -> Self {
+        if self != Precision::Auto {
+            return self;
+        }
+        let magnitude = centre.re.abs().max(centre.im.abs()) + pixel_map.extent(width, height);
+        let required_bits = (magnitude / pixel_map.spacing()).log2().ceil() as u32;
+        let fits = |mantissa_bits: u32| required_bits + PRECISION_GUARD_BITS <= mantissa_bits;
+        if fits(f64::MANTISSA_BITS) {
+            Precision::Double
+        } else {
+            Precision::DoubleDouble
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum SamplePattern {
+    /// evenly spaced, at the centres of a square grid over the pixel
+    Grid,
+    /// at a random point in each cell of the grid, which trades the grid's regular artifacts
+    /// for noise; the same every run
+    Jittered,
+}
+
+impl SamplePattern {
+    /// where each of `samples` renders is taken, as offsets in pixels from the middle of the
+    /// grid. a single grid sample is taken at no offset, to match the plain render.
+    pub fn shifts(self, samples: usize) -> Result<Vec<Complex>> {
+        let side = (samples as f64).sqrt().round() as usize;
+        if side == 0 || side * side != samples {
+            return Err(anyhow!(
+                "--samples must be a square number, e.g. 1, 4, 9 or 16"
+            ));
+        }
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut cell = |i: usize| {
+            let position = match self {
+                SamplePattern::Grid => 0.5,
+                SamplePattern::Jittered => rng.gen(),
+            };
+            (i as f64 + position) / side as f64 - 0.5
+        };
+        Ok((0..side)
+            .flat_map(|x| (0..side).map(move |y| (x, y)))
+            .map(|(x, y)| Complex::new(cell(x), cell(y)))
+            .collect())
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 pub enum PlottingAlgorithm {
     Vanilla,
     Smooth,
     Histogram,
     SmoothHistogram,
+    /// shade by estimated distance to the boundary, for crisp filaments
+    DistanceEstimate,
+    /// shade by how close each orbit comes to the shape given by --trap
+    OrbitTrap,
+    /// shade by the average angle of the orbit, drawing --stripe-density stripes
+    StripeAverage,
+    /// shade by the average of where each |z| lands between its triangle inequality bounds
+    TriangleInequalityAverage,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     Centre {
-        x: f64,
-        y: f64,
-        #[arg(short, long, default_value_t = 8)]
-        zoom: usize,
+        /// real part of the centre, to as many digits as the zoom needs
+        x: Coordinate,
+        /// imaginary part of the centre, to as many digits as the zoom needs
+        y: Coordinate,
+        /// magnification, e.g. `8`, `0.5`, `1e400` or `2^1500`
+        #[arg(short, long, default_value_t = Zoom::default())]
+        zoom: Zoom,
     },
     CentreString {
         /// name of corresponding NamedPoint in config.yaml
         name: String,
     },
+    /// view a centre, and how far the shorter side of the image reaches either side of it
+    CentreRadius {
+        #[arg(allow_negative_numbers = true)]
+        x: Coordinate,
+        #[arg(allow_negative_numbers = true)]
+        y: Coordinate,
+        radius: f64,
+    },
+    /// view a centre, with neighbouring pixels `pixel_size` apart
+    CentrePixelSize {
+        #[arg(allow_negative_numbers = true)]
+        x: Coordinate,
+        #[arg(allow_negative_numbers = true)]
+        y: Coordinate,
+        pixel_size: f64,
+    },
+    /// view the box between two opposite corners, before any --rotation, with room either side
+    /// of it if its aspect ratio isn't the image's
+    Corners {
+        #[arg(allow_negative_numbers = true)]
+        x1: f64,
+        #[arg(allow_negative_numbers = true)]
+        y1: f64,
+        #[arg(allow_negative_numbers = true)]
+        x2: f64,
+        #[arg(allow_negative_numbers = true)]
+        y2: f64,
+    },
+    /// plot the julia set for a fixed c, using each pixel as z_0
+    Julia {
+        #[arg(allow_negative_numbers = true)]
+        c_re: f64,
+        #[arg(allow_negative_numbers = true)]
+        c_im: f64,
+        #[arg(short, long, default_value_t = Coordinate::default(), allow_negative_numbers = true)]
+        x: Coordinate,
+        #[arg(short, long, default_value_t = Coordinate::default(), allow_negative_numbers = true)]
+        y: Coordinate,
+        /// magnification, e.g. `8`, `0.5`, `1e400` or `2^1500`
+        #[arg(short, long, default_value_t = Zoom::default())]
+        zoom: Zoom,
+    },
+    /// plot which root newton's method converges to from each point, for a polynomial given
+    /// either by its roots or its coefficients
+    Newton {
+        /// comma-separated roots of the polynomial, e.g. `1,-0.5+0.866i,-0.5-0.866i`
+        #[arg(
+            long,
+            value_delimiter = ',',
+            allow_hyphen_values = true,
+            required_unless_present = "coefficients",
+            conflicts_with = "coefficients"
+        )]
+        roots: Vec<Complex>,
+        /// comma-separated coefficients of the polynomial, starting from the constant term
+        #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+        coefficients: Vec<Complex>,
+        /// comma-separated palettes to colour each root's basin with, in turn;
+        /// defaults to one hue per root
+        #[arg(long, value_delimiter = ',')]
+        root_palettes: Vec<String>,
+        #[arg(short, long, default_value_t = 0., allow_negative_numbers = true)]
+        x: f64,
+        #[arg(short, long, default_value_t = 0., allow_negative_numbers = true)]
+        y: f64,
+        /// magnification, e.g. `8`, `0.5`, `1e400` or `2^1500`
+        #[arg(short, long, default_value_t = Zoom::default())]
+        zoom: Zoom,
+    },
+    /// plot how often the orbits of escaping points pass through each pixel: a buddhabrot,
+    /// or a nebulabrot if an iteration limit is given for each colour channel
+    Buddhabrot {
+        /// number of random c values to trace
+        #[arg(short, long, default_value_t = 10_000_000)]
+        samples: usize,
+        /// seed for picking c values; the same seed gives the same image
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// comma-separated iteration limits for the red, green and blue channels, e.g.
+        /// `5000,500,50`; defaults to colouring with the palette up to --max-iters
+        #[arg(long, value_delimiter = ',')]
+        channel_iters: Vec<usize>,
+        #[arg(short, long, default_value_t = -0.5, allow_negative_numbers = true)]
+        x: f64,
+        #[arg(short, long, default_value_t = 0.)]
+        y: f64,
+        /// magnification, e.g. `8`, `0.5`, `1e400` or `2^1500`
+        #[arg(short, long, default_value_t = Zoom::default())]
+        zoom: Zoom,
+    },
+    /// colour raw data saved with --raw-out again, without iterating
+    Recolor {
+        /// file saved with --raw-out
+        input: String,
+    },
 }
 
 #[derive(Debug)]
@@ -150,15 +1573,63 @@ impl Interval {
     }
 }
 
-pub fn get_intervals(centre: Complex, zoom: f64) -> (Interval, Interval) {
-    (
-        Interval {
-            lower: centre.re - 16. / zoom,
-            upper: centre.re + 16. / zoom,
-        },
-        Interval {
-            lower: centre.im - 9. / zoom,
-            upper: centre.im + 9. / zoom,
-        },
-    )
+/// pixels whose colour is further than `threshold` from one of their neighbours', measuring
+/// the straight-line distance between colours in rgb. these are where an image is aliased, and
+/// changes in escape count which don't show up in the colour don't need smoothing anyway.
+pub fn find_edges(image: &RgbImage, threshold: f64) -> Vec<(u32, u32)> {
+    let (width, height) = image.dimensions();
+    let distance = |a: &Rgb<u8>, b: &Rgb<u8>| {
+        a.0.iter()
+            .zip(b.0)
+            .map(|(&a, b)| (a as f64 - b as f64).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    };
+    (0..width)
+        .into_par_iter()
+        .flat_map_iter(|x| {
+            (0..height)
+                .filter(move |&y| {
+                    let pixel = image.get_pixel(x, y);
+                    [
+                        x.checked_sub(1).map(|x| (x, y)),
+                        (x + 1 < width).then_some((x + 1, y)),
+                        y.checked_sub(1).map(|y| (x, y)),
+                        (y + 1 < height).then_some((x, y + 1)),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .any(|(x, y)| distance(pixel, image.get_pixel(x, y)) > threshold)
+                })
+                .map(move |y| (x, y))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn julia_takes_negative_numbers() {
+        let cli = Cli::try_parse_from([
+            "mandelbust-cli",
+            "julia",
+            "-0.8",
+            "-0.156",
+            "-x",
+            "-0.5",
+            "-y",
+            "-0.001",
+        ])
+        .unwrap();
+        let Commands::Julia {
+            c_re, c_im, x, y, ..
+        } = cli.command
+        else {
+            panic!("parsed as {:?}", cli.command);
+        };
+        assert_eq!((c_re, c_im), (-0.8, -0.156));
+        assert_eq!((x.to_f64(), y.to_f64()), (-0.5, -0.001));
+    }
 }
@@ -114,3 +114,21 @@ impl ColorPalette {
         self
     }
 }
+
+/// converts a colour given as hue, saturation and value, each in [0, 1], to rgb
+pub fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> Rgb<u8> {
+    let sector = (hue.rem_euclid(1.) * 6.).min(6. - f64::EPSILON);
+    let chroma = value * saturation;
+    let x = chroma * (1. - (sector % 2. - 1.).abs());
+    let (r, g, b) = match sector as usize {
+        0 => (chroma, x, 0.),
+        1 => (x, chroma, 0.),
+        2 => (0., chroma, x),
+        3 => (0., x, chroma),
+        4 => (x, 0., chroma),
+        _ => (chroma, 0., x),
+    };
+    let m = value - chroma;
+    let to_u8 = |channel: f64| ((channel + m) * 255.).round() as u8;
+    Rgb([to_u8(r), to_u8(g), to_u8(b)])
+}
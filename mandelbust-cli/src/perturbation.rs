@@ -0,0 +1,457 @@
+use crate::coordinate::Point;
+use crate::field::Field;
+use crate::mandelbrot::{Complex, Escape, Exponent, Fractal};
+use crate::render::Renderer;
+use crate::view::PixelMap;
+use anyhow::{anyhow, Result};
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
+use rayon::prelude::*;
+
+/// a pixel is considered glitched once |z|^2 drops below this fraction of |Z|^2
+const GLITCH_TOLERANCE: f64 = 1e-6;
+/// upper bound on the number of reference orbits computed for a single image
+const MAX_REFERENCES: usize = 64;
+/// extra bits of precision kept beyond what's needed to resolve a single pixel
+const GUARD_BITS: u64 = 64;
+/// bits of room needed below the pixel spacing before the cardioid/bulb test is trusted in `f64`
+const BULB_TEST_GUARD_BITS: u32 = 8;
+
+/// fixed-point arithmetic on `BigInt`s with `bits` fractional bits. only used for the
+/// reference orbit, so it doesn't need to be fast.
+struct FixedPoint {
+    bits: u64,
+}
+
+#[derive(Clone)]
+struct BigComplex {
+    re: BigInt,
+    im: BigInt,
+}
+
+impl FixedPoint {
+    /// enough bits to resolve pixels `pixel_spacing` apart, or an error if that isn't a
+    /// positive, finite distance
+    fn for_spacing(pixel_spacing: f64) -> Result<Self> {
+        if !(pixel_spacing.is_finite() && pixel_spacing > 0.) {
+            return Err(anyhow!(
+                "pixels {} apart can't be rendered with --deep-zoom",
+                pixel_spacing
+            ));
+        }
+        let pixel_bits = (-pixel_spacing.log2()).max(0.).ceil() as u64;
+        let bits = pixel_bits
+            .checked_add(GUARD_BITS)
+            .ok_or_else(|| anyhow!("pixels {} apart need too many bits", pixel_spacing))?;
+        Ok(Self { bits })
+    }
+
+    fn fixed(&self, val: f64) -> BigInt {
+        if val == 0. {
+            return BigInt::zero();
+        }
+        let raw = val.to_bits();
+        let raw_exponent = ((raw >> 52) & 0x7ff) as i64;
+        let fraction = raw & ((1 << 52) - 1);
+        // val = mantissa * 2^exponent, exactly
+        let (mantissa, exponent) = if raw_exponent == 0 {
+            (fraction, -1074)
+        } else {
+            (fraction | (1 << 52), raw_exponent - 1075)
+        };
+        let shift = exponent + self.bits as i64;
+        let magnitude = BigInt::from(mantissa);
+        let magnitude = if shift >= 0 {
+            magnitude << shift as usize
+        } else {
+            magnitude >> (-shift) as usize
+        };
+        if val.is_sign_negative() {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+
+    fn to_f64(&self, val: &BigInt) -> f64 {
+        let excess = val.bits().saturating_sub(64);
+        let top = (val >> excess).to_f64().unwrap_or(0.);
+        top * 2_f64.powi(excess as i32 - self.bits as i32)
+    }
+
+    fn complex(&self, val: Complex) -> BigComplex {
+        BigComplex {
+            re: self.fixed(val.re),
+            im: self.fixed(val.im),
+        }
+    }
+
+    /// `point` to the nearest fixed point, keeping the digits an `f64` would lose
+    fn point(&self, point: &Point) -> BigComplex {
+        BigComplex {
+            re: point.re.fixed(self.bits),
+            im: point.im.fixed(self.bits),
+        }
+    }
+
+    fn to_complex(&self, val: &BigComplex) -> Complex {
+        Complex::new(self.to_f64(&val.re), self.to_f64(&val.im))
+    }
+
+    fn mul(&self, a: &BigComplex, b: &BigComplex) -> BigComplex {
+        BigComplex {
+            re: ((&a.re * &b.re) - (&a.im * &b.im)) >> self.bits,
+            im: ((&a.re * &b.im) + (&a.im * &b.re)) >> self.bits,
+        }
+    }
+
+    fn powi(&self, z: &BigComplex, degree: u32) -> BigComplex {
+        let mut base = z.clone();
+        let mut acc: Option<BigComplex> = None;
+        let mut n = degree;
+        while n > 0 {
+            if n & 1 == 1 {
+                acc = Some(match acc {
+                    Some(acc) => self.mul(&acc, &base),
+                    None => base.clone(),
+                });
+            }
+            n >>= 1;
+            if n > 0 {
+                base = self.mul(&base, &base);
+            }
+        }
+        acc.expect("degree is at least 2")
+    }
+
+    /// `Fractal::iterate` at full precision
+    fn iterate(&self, fractal: Fractal, z: BigComplex, c: &BigComplex, degree: u32) -> BigComplex {
+        let z = match fractal {
+            Fractal::BurningShip => BigComplex {
+                re: z.re.abs(),
+                im: z.im.abs(),
+            },
+            Fractal::Tricorn => BigComplex {
+                re: z.re,
+                im: -z.im,
+            },
+            _ => z,
+        };
+        let w = self.powi(&z, degree);
+        let w = match fractal {
+            Fractal::Celtic => BigComplex {
+                re: w.re.abs(),
+                im: w.im,
+            },
+            Fractal::Buffalo => BigComplex {
+                re: w.re.abs(),
+                im: w.im.abs(),
+            },
+            _ => w,
+        };
+        BigComplex {
+            re: w.re + &c.re,
+            im: w.im + &c.im,
+        }
+    }
+
+    /// iterates `fractal` from `z_0` at full precision, returning every value of the orbit as
+    /// `f64`s. the orbit stops early if the reference point escapes.
+    fn reference_orbit(
+        &self,
+        fractal: Fractal,
+        c: &BigComplex,
+        z_0: &BigComplex,
+        degree: u32,
+        max_iters: usize,
+        bailout_sq: f64,
+    ) -> Vec<Complex> {
+        let mut orbit = Vec::with_capacity(max_iters + 1);
+        let mut z = z_0.clone();
+        orbit.push(self.to_complex(&z));
+        for _ in 1..=max_iters {
+            z = self.iterate(fractal, z, c, degree);
+            let z_f64 = self.to_complex(&z);
+            orbit.push(z_f64);
+            if z_f64.abs_value_sq() > bailout_sq {
+                break;
+            }
+        }
+        orbit
+    }
+}
+
+/// binomial coefficients (d choose k) for k in 0..=d
+fn binomials(degree: u32) -> Vec<f64> {
+    let mut row = vec![1.];
+    for k in 1..=degree {
+        let prev = row[k as usize - 1];
+        row.push(prev * (degree - k + 1) as f64 / k as f64);
+    }
+    row
+}
+
+/// (Z + delta)^d - Z^d, expanded binomially so that it stays accurate when delta is tiny next
+/// to Z. evaluated with horner's method in delta
+fn perturbed_power(reference: Complex, delta: Complex, binomials: &[f64]) -> Complex {
+    let degree = binomials.len() - 1;
+    let mut sum = Complex::new(binomials[degree], 0.);
+    let mut reference_pow = Complex::new(1., 0.);
+    for k in (1..degree).rev() {
+        reference_pow = reference_pow * reference;
+        sum = sum * delta + reference_pow.scale(binomials[k]);
+    }
+    sum * delta
+}
+
+/// |x + delta| - |x|, without the cancellation you'd get from evaluating it directly
+fn diffabs(x: f64, delta: f64) -> f64 {
+    if x >= 0. {
+        if x + delta >= 0. {
+            delta
+        } else {
+            -(2. * x + delta)
+        }
+    } else if x + delta > 0. {
+        2. * x + delta
+    } else {
+        -delta
+    }
+}
+
+/// the change in `Fractal::iterate` (minus c) when its argument moves from `reference` to
+/// `reference + delta`
+fn perturbed_iterate(
+    fractal: Fractal,
+    reference: Complex,
+    delta: Complex,
+    binomials: &[f64],
+) -> Complex {
+    let degree = binomials.len() as i32 - 1;
+    match fractal {
+        Fractal::Mandelbrot => perturbed_power(reference, delta, binomials),
+        Fractal::BurningShip => {
+            let folded_delta = Complex::new(
+                diffabs(reference.re, delta.re),
+                diffabs(reference.im, delta.im),
+            );
+            perturbed_power(reference.abs_parts(), folded_delta, binomials)
+        }
+        Fractal::Tricorn => perturbed_power(reference.conj(), delta.conj(), binomials),
+        Fractal::Celtic => {
+            let w = perturbed_power(reference, delta, binomials);
+            let reference_w = reference.powi(degree);
+            Complex::new(diffabs(reference_w.re, w.re), w.im)
+        }
+        Fractal::Buffalo => {
+            let w = perturbed_power(reference, delta, binomials);
+            let reference_w = reference.powi(degree);
+            Complex::new(diffabs(reference_w.re, w.re), diffabs(reference_w.im, w.im))
+        }
+    }
+}
+
+/// iterates the difference between a pixel and a reference orbit in plain `f64`s. the
+/// returned flag is set when the result can't be trusted and the pixel needs a new reference.
+/// `derivative` is the starting derivative and the amount added to it each step, if it should
+/// be tracked. it only needs the full z, which `f64` holds well enough, so isn't perturbed.
+#[allow(clippy::too_many_arguments)]
+fn perturbed_escape_count(
+    fractal: Fractal,
+    orbit: &[Complex],
+    delta_0: Complex,
+    delta_c: Complex,
+    binomials: &[f64],
+    bailout: f64,
+    max_iters: usize,
+    derivative: Option<(Complex, Complex)>,
+) -> (Escape, bool) {
+    let (mut dz, dz_step) = derivative.unzip();
+    let escape = |iters, z, dz| Escape {
+        derivative: dz,
+        ..Escape::new(iters, z)
+    };
+    if (orbit[0] + delta_0).abs_value_sq() > bailout {
+        return (escape(0, orbit[0] + delta_0, dz), false);
+    }
+    let exponent = Exponent::Integer(binomials.len() as i32 - 1);
+    let bailout_sq = bailout.powf(2.);
+    let mut delta = delta_0;
+    for iter in 1..=max_iters {
+        let reference = orbit[iter - 1];
+        if let (Some(dz), Some(step)) = (dz.as_mut(), dz_step) {
+            *dz = (reference + delta).multibrot_derivative(exponent) * *dz + step;
+        }
+        delta = perturbed_iterate(fractal, reference, delta, binomials) + delta_c;
+        let Some(&next_reference) = orbit.get(iter) else {
+            // the reference escaped before this pixel did
+            return (escape(iter, reference + delta, dz), true);
+        };
+        let z = next_reference + delta;
+        let z_sq = z.abs_value_sq();
+        if z_sq > bailout_sq {
+            return (escape(iter, z, dz), false);
+        }
+        if z_sq < GLITCH_TOLERANCE * next_reference.abs_value_sq() {
+            return (escape(iter, z, dz), true);
+        }
+    }
+    (escape(max_iters, orbit[max_iters] + delta, dz), false)
+}
+
+/// like `generate_escape_counts`, but `pixel_map`'s offsets are relative to `centre`, so the
+/// view can be far smaller than the spacing between neighbouring `f64`s. `centre` is only
+/// rounded to the precision the reference orbit is worked out with.
+/// pixels which glitch are re-rendered against a new reference orbit chosen among them. those
+/// still glitched after `MAX_REFERENCES` orbits are kept, and counted on `renderer`.
+/// for julia sets the reference orbit perturbs z_0 rather than c. only integer exponents of at
+/// least 2 can be perturbed this way. `interior_checks` only applies the cardioid/bulb test, and
+/// only while `f64` can still resolve the pixels: deltas are far below any tolerance a
+/// periodicity check could use, so pixels would be wrongly marked as inside. with `derivative`
+/// set, the `Escape`s passed to `post_fn` carry derivatives, which assumes an analytic
+/// `fractal` such as `Fractal::Mandelbrot`.
+///
+/// the first round, against the centre's reference orbit, covers every pixel, so it's run as a
+/// tiled pass on `renderer`, which counts its progress. the rounds fixing glitched pixels only
+/// check for cancellation between them, as their references are picked from across the whole
+/// image.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_perturbed_escape_counts<F, R>(
+    centre: &Point,
+    julia: Option<Complex>,
+    fractal: Fractal,
+    degree: u32,
+    pixel_map: &PixelMap,
+    width: usize,
+    height: usize,
+    max_iters: usize,
+    bailout: f64,
+    interior_checks: bool,
+    derivative: bool,
+    renderer: &Renderer,
+    post_fn: F,
+) -> Result<Field<R>>
+where
+    R: Send,
+    F: Fn(Escape) -> R + std::marker::Sync,
+{
+    let fixed = FixedPoint::for_spacing(pixel_map.spacing())?;
+    let bailout_sq = bailout.powf(2.);
+    let binomials = binomials(degree);
+    let pixel_delta = |i: usize| {
+        let (x, y) = (i % width, i / width);
+        pixel_map.offset(x as f64, y as f64)
+    };
+
+    let centre_big = fixed.point(centre);
+    let centre = centre.to_complex();
+    let julia_big = julia.map(|c| fixed.complex(c));
+    let zero = fixed.complex(Complex::id());
+    let reference_orbit = |reference: &BigComplex| match &julia_big {
+        Some(c) => fixed.reference_orbit(fractal, c, reference, degree, max_iters, bailout_sq),
+        None => fixed.reference_orbit(fractal, reference, &zero, degree, max_iters, bailout_sq),
+    };
+    let resolvable = pixel_map.spacing()
+        > f64::EPSILON
+            * (1 << BULB_TEST_GUARD_BITS) as f64
+            * centre.re.abs().max(centre.im.abs()).max(1.);
+    let skip_interior = interior_checks
+        && resolvable
+        && julia.is_none()
+        && fractal == Fractal::Mandelbrot
+        && degree == 2;
+
+    // dz/dz_0 starts at 1 for julia sets, while dz/dc starts at 0 and gains 1 each step
+    let one = Complex::new(1., 0.);
+    let derivative_start = match julia {
+        Some(_) => (one, Complex::id()),
+        None => (Complex::id(), one),
+    };
+    let escape_against = |orbit: &[Complex], reference_delta: Complex, i: usize| {
+        let delta = pixel_delta(i) - reference_delta;
+        let (delta_0, delta_c) = match julia {
+            Some(_) => (delta, Complex::id()),
+            None => (Complex::id(), delta),
+        };
+        perturbed_escape_count(
+            fractal,
+            orbit,
+            delta_0,
+            delta_c,
+            &binomials,
+            bailout,
+            max_iters,
+            derivative.then_some(derivative_start),
+        )
+    };
+
+    renderer.check()?;
+    let orbit = reference_orbit(&centre_big);
+    let first_round = renderer.render(width, height, true, |tile| {
+        let escapes = tile
+            .pixels()
+            .map(|(x, y)| {
+                let i = y * width + x;
+                if skip_interior && (centre + pixel_delta(i)).in_main_bulbs() {
+                    (Escape::new(max_iters, Complex::id()), false)
+                } else {
+                    escape_against(&orbit, Complex::id(), i)
+                }
+            })
+            .collect();
+        Field::new(tile.width, tile.height, escapes)
+    })?;
+    let (mut results, glitched): (Vec<Escape>, Vec<bool>) =
+        first_round.into_values().into_iter().unzip();
+    let mut pending: Vec<usize> = (0..width * height).filter(|&i| glitched[i]).collect();
+
+    for _ in 1..MAX_REFERENCES {
+        if pending.is_empty() {
+            break;
+        }
+        renderer.check()?;
+
+        // pick the glitched pixel closest to the middle of all glitched pixels as the next
+        // reference. the reference can't glitch against itself, so this always makes progress
+        let mean = pending
+            .iter()
+            .fold(Complex::id(), |acc, &i| acc + pixel_delta(i));
+        let mean = Complex::new(
+            mean.re / pending.len() as f64,
+            mean.im / pending.len() as f64,
+        );
+        let &next = pending
+            .iter()
+            .min_by(|&&a, &&b| {
+                let dist_a = (pixel_delta(a) - mean).abs_value_sq();
+                let dist_b = (pixel_delta(b) - mean).abs_value_sq();
+                dist_a.partial_cmp(&dist_b).unwrap()
+            })
+            .expect("pending is non-empty");
+        let reference_delta = pixel_delta(next);
+        let offset = fixed.complex(reference_delta);
+        let orbit = reference_orbit(&BigComplex {
+            re: &centre_big.re + offset.re,
+            im: &centre_big.im + offset.im,
+        });
+
+        let computed: Vec<(usize, (Escape, bool))> = pending
+            .par_iter()
+            .map(|&i| (i, escape_against(&orbit, reference_delta, i)))
+            .collect();
+        pending.clear();
+        for (i, (escape, glitched)) in computed {
+            results[i] = escape;
+            if glitched {
+                pending.push(i);
+            }
+        }
+    }
+
+    renderer.add_glitched(pending.len());
+    Ok(Field::new(
+        width,
+        height,
+        results.into_par_iter().map(&post_fn).collect(),
+    ))
+}
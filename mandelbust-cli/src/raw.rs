@@ -0,0 +1,206 @@
+use crate::coordinate::Point;
+use crate::field::Field;
+use crate::mandelbrot::{Complex, Fractal};
+use crate::transform::Transform;
+use crate::view::Orientation;
+use crate::zoom::Zoom;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+
+/// the first bytes of every raw file
+const MAGIC: &[u8; 8] = b"MBSTRAW\0";
+/// bumped whenever the layout changes, so old files are rejected rather than misread
+const VERSION: u32 = 2;
+
+/// escape counts, as whole numbers
+pub const ITERS: &str = "iters";
+/// escape counts with the fractional part from smooth colouring
+pub const SMOOTH: &str = "smooth";
+/// the first iterate outside the bailout radius, or wherever iteration stopped
+pub const Z_RE: &str = "z.re";
+pub const Z_IM: &str = "z.im";
+/// hues from --algorithm stripe-average
+pub const STRIPE_AVERAGE: &str = "stripe-average";
+/// hues from --algorithm triangle-inequality-average
+pub const TRIANGLE_INEQUALITY_AVERAGE: &str = "triangle-inequality-average";
+/// brightness from --lighting
+pub const LIGHT: &str = "light";
+
+/// the parts of a pixel's `Escape` kept in raw data
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RawEscape {
+    pub iters: f64,
+    pub smooth: f64,
+    pub z_re: f64,
+    pub z_im: f64,
+    /// the orbit average's hue, if one was taken
+    pub average: f64,
+    /// the brightness from --lighting, if it's on
+    pub light: f64,
+}
+
+/// what was rendered to get some raw data
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RawParams {
+    pub fractal: Fractal,
+    pub exponent: f64,
+    pub centre: Point,
+    pub zoom: Zoom,
+    pub orientation: Orientation,
+    pub transform: Transform,
+    pub julia: Option<Complex>,
+    pub max_iters: usize,
+    pub bailout: f64,
+    pub deep_zoom: bool,
+}
+
+/// the values of an escape-time render before they're coloured, so they can be coloured again
+/// without iterating. each channel is a named field of values over the whole image.
+///
+/// on disk this is `MAGIC`, then the little-endian `VERSION`, the params as yaml prefixed by
+/// their length, the width and height, and the number of channels, followed by each channel's
+/// name prefixed by its length and its values as `f64`s row by row.
+#[derive(Clone, Debug)]
+pub struct RawData {
+    pub params: RawParams,
+    width: usize,
+    height: usize,
+    channels: Vec<(String, Field<f64>)>,
+}
+
+impl RawData {
+    pub fn new(params: RawParams, width: usize, height: usize) -> Self {
+        Self {
+            params,
+            width,
+            height,
+            channels: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// adds a channel, replacing any with the same name
+    pub fn push(&mut self, name: &str, values: Field<f64>) {
+        assert_eq!(
+            (values.width(), values.height()),
+            (self.width, self.height),
+            "channel is the wrong size"
+        );
+        self.channels.retain(|(existing, _)| existing != name);
+        self.channels.push((name.to_string(), values));
+    }
+
+    pub fn channel(&self, name: &str) -> Option<&Field<f64>> {
+        self.channels
+            .iter()
+            .find(|(existing, _)| existing == name)
+            .map(|(_, values)| values)
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let file = File::create(path).with_context(|| format!("couldn't create {}", path))?;
+        let mut writer = BufWriter::new(file);
+        self.write_to(&mut writer)
+            .and_then(|_| writer.flush().map_err(Into::into))
+            .with_context(|| format!("problem saving raw data to {}", path))
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("couldn't open {}", path))?;
+        Self::read_from(&mut BufReader::new(file))
+            .with_context(|| format!("problem reading raw data from {}", path))
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        write_bytes(writer, serde_yaml::to_string(&self.params)?.as_bytes())?;
+        writer.write_all(&(self.width as u64).to_le_bytes())?;
+        writer.write_all(&(self.height as u64).to_le_bytes())?;
+        writer.write_all(&(self.channels.len() as u32).to_le_bytes())?;
+        for (name, values) in &self.channels {
+            write_bytes(writer, name.as_bytes())?;
+            for value in values.values() {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(anyhow!("not a raw data file"));
+        }
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(anyhow!(
+                "raw data is version {}, but only version {} can be read",
+                version,
+                VERSION
+            ));
+        }
+        let params = serde_yaml::from_slice(&read_bytes(reader)?)?;
+        let width = read_u64(reader)? as usize;
+        let height = read_u64(reader)? as usize;
+        let channel_len = width
+            .checked_mul(height)
+            .and_then(|len| len.checked_mul(8))
+            .ok_or_else(|| anyhow!("raw data is too big"))?;
+        let mut raw = Self::new(params, width, height);
+        for _ in 0..read_u32(reader)? {
+            let name = String::from_utf8(read_bytes(reader)?)?;
+            let bytes = read_len(reader, channel_len as u64)?;
+            let values = bytes
+                .chunks_exact(8)
+                .map(|b| f64::from_le_bytes(b.try_into().expect("chunks are 8 bytes")))
+                .collect();
+            raw.push(&name, Field::new(width, height, values));
+        }
+        Ok(raw)
+    }
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<()> {
+    writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    writer.write_all(bytes)?;
+    Ok(())
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_bytes<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    let len = read_u32(reader)?;
+    read_len(reader, len as u64)
+}
+
+/// reads exactly `len` bytes. the buffer grows as they arrive rather than being allocated up
+/// front, so a corrupt length can't ask for more memory than the file holds
+fn read_len<R: Read>(reader: &mut R, len: u64) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.by_ref().take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(anyhow!("raw data ends early"));
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,312 @@
+use crate::field::{Field, Tile};
+use rayon::prelude::*;
+use std::fmt;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// side of the square tiles passes are split into, in pixels
+const TILE_SIZE: usize = 64;
+
+/// width of the bar drawn by `ProgressBar`, in characters
+const BAR_WIDTH: usize = 30;
+
+/// a handle for stopping a render from another thread. clones share the same flag, so one can
+/// be handed to the `Renderer` and another kept to cancel it with.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// stops the render at the next tile; tiles already being worked on are finished first
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// the error a render returns when its `CancelToken` is cancelled
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "render cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// how far through a render is, counting a pixel once for each pass it's computed in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Progress {
+    pub pixels_done: usize,
+    pub pixels_total: usize,
+}
+
+impl Progress {
+    /// the share of the render done, between 0 and 1
+    pub fn fraction(self) -> f64 {
+        if self.pixels_total == 0 {
+            return 1.;
+        }
+        (self.pixels_done as f64 / self.pixels_total as f64).min(1.)
+    }
+}
+
+/// a tile of values finishing, passed to `Renderer::on_tile`
+#[derive(Clone, Copy, Debug)]
+pub struct TileDone<'a> {
+    /// which of the render's passes the tile belongs to, counting from 0. a render makes a pass
+    /// for each of --samples.
+    pub pass: usize,
+    pub tile: Tile,
+    /// the tile's values, row by row
+    pub values: &'a [f64],
+}
+
+type ProgressCallback<'a> = Box<dyn Fn(Progress) + Sync + 'a>;
+type TileCallback<'a> = Box<dyn Fn(TileDone) + Sync + 'a>;
+
+/// runs a render a tile at a time, reporting progress as tiles finish and stopping early if
+/// cancelled. a render is made up of passes, each of which computes a field over the whole
+/// image; the tiles of a pass are worked on in parallel.
+pub struct Renderer<'a> {
+    cancel: CancelToken,
+    on_progress: Option<ProgressCallback<'a>>,
+    on_tile: Option<TileCallback<'a>>,
+    passes: AtomicUsize,
+    pixels_done: AtomicUsize,
+    pixels_total: AtomicUsize,
+    glitched: AtomicUsize,
+}
+
+impl Default for Renderer<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new() -> Self {
+        Self {
+            cancel: CancelToken::new(),
+            on_progress: None,
+            on_tile: None,
+            passes: AtomicUsize::new(0),
+            pixels_done: AtomicUsize::new(0),
+            pixels_total: AtomicUsize::new(0),
+            glitched: AtomicUsize::new(0),
+        }
+    }
+
+    /// stops the render once `token` is cancelled
+    pub fn cancel_token(mut self, token: CancelToken) -> Self {
+        self.cancel = token;
+        self
+    }
+
+    /// calls `f` each time a tile finishes. it's called from whichever thread finished the
+    /// tile, so calls can arrive slightly out of order.
+    pub fn on_progress(mut self, f: impl Fn(Progress) + Sync + 'a) -> Self {
+        self.on_progress = Some(Box::new(f));
+        self
+    }
+
+    /// calls `f` with the values of each tile of escape counts or hues as it finishes, for
+    /// showing a render as it comes in. deep zooms don't report their tiles, as glitched pixels
+    /// in them are only fixed once the whole image is done
+    pub fn on_tile(mut self, f: impl Fn(TileDone) + Sync + 'a) -> Self {
+        self.on_tile = Some(Box::new(f));
+        self
+    }
+
+    /// starts counting progress from nothing, out of `pixels_total` pixels across all passes
+    pub fn begin(&self, pixels_total: usize) {
+        self.passes.store(0, Ordering::Relaxed);
+        self.pixels_done.store(0, Ordering::Relaxed);
+        self.pixels_total.store(pixels_total, Ordering::Relaxed);
+        self.glitched.store(0, Ordering::Relaxed);
+    }
+
+    /// how many pixels deep zooms have given up on fixing since `begin`, counting a pixel once
+    /// for each pass it's left glitched in. they're kept, but may be coloured wrongly.
+    pub fn glitched(&self) -> usize {
+        self.glitched.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_glitched(&self, pixels: usize) {
+        self.glitched.fetch_add(pixels, Ordering::Relaxed);
+    }
+
+    /// `Err(Cancelled)` once the render's been cancelled, for work done outside of passes
+    pub fn check(&self) -> Result<(), Cancelled> {
+        if self.cancel.is_cancelled() {
+            Err(Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// computes a pass over a `width` by `height` image by calling `f` for each tile, which
+    /// returns a field the size of the tile. without `tiled`, the whole image is done as a
+    /// single tile, for work which can't be split up.
+    pub fn render<T, F>(
+        &self,
+        width: usize,
+        height: usize,
+        tiled: bool,
+        f: F,
+    ) -> Result<Field<T>, Cancelled>
+    where
+        T: Send,
+        F: Fn(Tile) -> Field<T> + Sync,
+    {
+        self.run(width, height, tiled, f, |_, _, _| {})
+    }
+
+    /// `render` for a pass of any values, which are passed to the `on_tile` callback as the
+    /// `f64`s `to_value` turns them into
+    pub fn render_mapped<T, F, V>(
+        &self,
+        width: usize,
+        height: usize,
+        tiled: bool,
+        f: F,
+        to_value: V,
+    ) -> Result<Field<T>, Cancelled>
+    where
+        T: Send,
+        F: Fn(Tile) -> Field<T> + Sync,
+        V: Fn(&T) -> f64 + Sync,
+    {
+        self.run(width, height, tiled, f, |pass, tile, field: &Field<T>| {
+            if let Some(on_tile) = &self.on_tile {
+                let values: Vec<f64> = field.values().iter().map(&to_value).collect();
+                on_tile(TileDone {
+                    pass,
+                    tile,
+                    values: &values,
+                });
+            }
+        })
+    }
+
+    fn run<T, F, G>(
+        &self,
+        width: usize,
+        height: usize,
+        tiled: bool,
+        f: F,
+        done: G,
+    ) -> Result<Field<T>, Cancelled>
+    where
+        T: Send,
+        F: Fn(Tile) -> Field<T> + Sync,
+        G: Fn(usize, Tile, &Field<T>) + Sync,
+    {
+        let pass = self.passes.fetch_add(1, Ordering::Relaxed);
+        let size = if tiled { TILE_SIZE } else { width.max(height) };
+        let tiles: Vec<_> = Tile::grid(width, height, size).collect();
+        let fields = tiles
+            .into_par_iter()
+            .map(|tile| {
+                self.check()?;
+                let field = f(tile);
+                assert_eq!(
+                    (field.width(), field.height()),
+                    (tile.width, tile.height),
+                    "tile rendered at the wrong size"
+                );
+                done(pass, tile, &field);
+                self.advance(tile.width * tile.height);
+                Ok((tile, field))
+            })
+            .collect::<Result<Vec<_>, Cancelled>>()?;
+        Ok(assemble(width, height, fields))
+    }
+
+    fn advance(&self, pixels: usize) {
+        let pixels_done = self.pixels_done.fetch_add(pixels, Ordering::Relaxed) + pixels;
+        // passes which weren't counted in `begin` still get reported, just with a growing total
+        let pixels_total = self
+            .pixels_total
+            .fetch_max(pixels_done, Ordering::Relaxed)
+            .max(pixels_done);
+        if let Some(on_progress) = &self.on_progress {
+            on_progress(Progress {
+                pixels_done,
+                pixels_total,
+            });
+        }
+    }
+}
+
+/// stitches the fields of tiles, in the order `Tile::grid` gives them, back into one field
+fn assemble<T>(width: usize, height: usize, fields: Vec<(Tile, Field<T>)>) -> Field<T> {
+    let mut values = Vec::with_capacity(width * height);
+    let mut fields = fields.into_iter().peekable();
+    while let Some((first, field)) = fields.next() {
+        // every tile in a band of the grid starts on the same row
+        let mut band = vec![(first, field.into_values().into_iter())];
+        while let Some((tile, _)) = fields.peek() {
+            if tile.y != first.y {
+                break;
+            }
+            let (tile, field) = fields.next().expect("peeked");
+            band.push((tile, field.into_values().into_iter()));
+        }
+        for _ in 0..first.height {
+            for (tile, values_left) in &mut band {
+                values.extend(values_left.by_ref().take(tile.width));
+            }
+        }
+    }
+    Field::new(width, height, values)
+}
+
+/// draws progress as a bar on stderr, only redrawing when the percentage changes
+pub struct ProgressBar {
+    label: String,
+    percent: Mutex<Option<usize>>,
+}
+
+impl ProgressBar {
+    pub fn new(label: impl Into<String>) -> Self {
+        Self {
+            label: label.into(),
+            percent: Mutex::new(None),
+        }
+    }
+
+    pub fn update(&self, progress: Progress) {
+        let percent = (progress.fraction() * 100.) as usize;
+        let mut last = self.percent.lock().unwrap();
+        if *last == Some(percent) {
+            return;
+        }
+        *last = Some(percent);
+        let filled = percent * BAR_WIDTH / 100;
+        eprint!(
+            "\r{} [{}{}] {:>3}%",
+            self.label,
+            "#".repeat(filled),
+            " ".repeat(BAR_WIDTH - filled),
+            percent
+        );
+        let _ = std::io::stderr().flush();
+    }
+
+    /// ends the bar's line, so whatever's printed next starts on its own
+    pub fn finish(&self) {
+        if self.percent.lock().unwrap().is_some() {
+            eprintln!();
+        }
+    }
+}
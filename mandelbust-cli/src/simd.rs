@@ -0,0 +1,268 @@
+#[cfg(target_arch = "x86_64")]
+use crate::mandelbrot::{periodicity_tolerance, Exponent, Fractal};
+use crate::mandelbrot::{Complex, Escape};
+#[cfg(target_arch = "x86_64")]
+use crate::numeric::Real;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+/// generates an AVX version of `Complex::escape_count` for z -> z^2 + c, iterating a vector's
+/// worth of points in lockstep. every lane does exactly the same floating point operations, in
+/// the same order, as the scalar loop (no fused multiply-adds), so the results are bit-identical.
+/// lanes which escape or are caught by the periodicity check are masked off and frozen.
+#[cfg(target_arch = "x86_64")]
+macro_rules! avx_escape_counts {
+    (
+        $name:ident,
+        $real:ty,
+        $lanes:literal,
+        $set1:ident,
+        $loadu:ident,
+        $storeu:ident,
+        $add:ident,
+        $sub:ident,
+        $mul:ident,
+        $cmp:ident,
+        $blendv:ident,
+        $and:ident,
+        $andnot:ident,
+        $movemask:ident
+    ) => {
+        #[target_feature(enable = "avx")]
+        unsafe fn $name(
+            points: &[(Complex<$real>, Complex<$real>)],
+            bound: f64,
+            max_iters: usize,
+            interior_checks: bool,
+        ) -> Vec<Escape<$real>> {
+            let bound_sq = $set1(<$real>::from_f64(bound.powf(2.)));
+            let tolerance = $set1(periodicity_tolerance::<$real>());
+            let two = $set1(2.);
+            let mut results = Vec::with_capacity(points.len());
+            for chunk in points.chunks($lanes) {
+                // short chunks are padded by repeating their first point, and the extra lanes
+                // thrown away at the end
+                let lane = |i: usize| chunk.get(i).unwrap_or(&chunk[0]);
+                let mut lane_results = [Escape::new(max_iters, Complex::id()); $lanes];
+                let mut active = [1.; $lanes];
+                let (mut c_re, mut c_im, mut z_re, mut z_im) =
+                    ([0.; $lanes], [0.; $lanes], [0.; $lanes], [0.; $lanes]);
+                for i in 0..$lanes {
+                    let &(c, z_0) = lane(i);
+                    (c_re[i], c_im[i], z_re[i], z_im[i]) = (c.re, c.im, z_0.re, z_0.im);
+                    lane_results[i].z = z_0;
+                    if let Some(result) = c.known_escape_count(
+                        z_0,
+                        Fractal::Mandelbrot,
+                        Exponent::Integer(2),
+                        bound,
+                        max_iters,
+                        interior_checks,
+                    ) {
+                        lane_results[i] = result;
+                        active[i] = 0.;
+                    }
+                }
+
+                let c_re = $loadu(c_re.as_ptr());
+                let c_im = $loadu(c_im.as_ptr());
+                let mut z_re = $loadu(z_re.as_ptr());
+                let mut z_im = $loadu(z_im.as_ptr());
+                let mut active = $cmp::<_CMP_EQ_OQ>($loadu(active.as_ptr()), $set1(1.));
+                let (mut saved_re, mut saved_im) = (z_re, z_im);
+                let mut period = 1;
+                let mut since_saved = 0;
+                // writes the lanes set in `mask` into `lane_results`
+                let mut finish = |mask: i32, iters: usize, z_re, z_im| {
+                    let (mut re, mut im) = ([0.; $lanes], [0.; $lanes]);
+                    $storeu(re.as_mut_ptr(), z_re);
+                    $storeu(im.as_mut_ptr(), z_im);
+                    for i in (0..$lanes).filter(|i| mask & (1 << i) != 0) {
+                        lane_results[i] = Escape::new(iters, Complex::new(re[i], im[i]));
+                    }
+                };
+
+                for iter in 1..=max_iters {
+                    if $movemask(active) == 0 {
+                        break;
+                    }
+                    let next_re = $add($mul($sub(z_re, z_im), $add(z_re, z_im)), c_re);
+                    let next_im = $add($mul($mul(two, z_re), z_im), c_im);
+                    z_re = $blendv(z_re, next_re, active);
+                    z_im = $blendv(z_im, next_im, active);
+
+                    let abs_sq = $add($mul(z_re, z_re), $mul(z_im, z_im));
+                    let escaped = $and($cmp::<_CMP_GT_OQ>(abs_sq, bound_sq), active);
+                    active = $andnot(escaped, active);
+                    let escaped = $movemask(escaped);
+                    if escaped != 0 {
+                        finish(escaped, iter, z_re, z_im);
+                    }
+
+                    if interior_checks {
+                        let diff_re = $sub(z_re, saved_re);
+                        let diff_im = $sub(z_im, saved_im);
+                        let diff_sq = $add($mul(diff_re, diff_re), $mul(diff_im, diff_im));
+                        let cycled = $and($cmp::<_CMP_LT_OQ>(diff_sq, tolerance), active);
+                        active = $andnot(cycled, active);
+                        let cycled = $movemask(cycled);
+                        if cycled != 0 {
+                            finish(cycled, max_iters, z_re, z_im);
+                        }
+                        since_saved += 1;
+                        if since_saved == period {
+                            (saved_re, saved_im) = (z_re, z_im);
+                            since_saved = 0;
+                            period *= 2;
+                        }
+                    }
+                }
+                finish($movemask(active), max_iters, z_re, z_im);
+                results.extend_from_slice(&lane_results[..chunk.len()]);
+            }
+            results
+        }
+    };
+}
+
+#[cfg(target_arch = "x86_64")]
+avx_escape_counts!(
+    avx_escape_counts_f64,
+    f64,
+    4,
+    _mm256_set1_pd,
+    _mm256_loadu_pd,
+    _mm256_storeu_pd,
+    _mm256_add_pd,
+    _mm256_sub_pd,
+    _mm256_mul_pd,
+    _mm256_cmp_pd,
+    _mm256_blendv_pd,
+    _mm256_and_pd,
+    _mm256_andnot_pd,
+    _mm256_movemask_pd
+);
+
+#[cfg(target_arch = "x86_64")]
+avx_escape_counts!(
+    avx_escape_counts_f32,
+    f32,
+    8,
+    _mm256_set1_ps,
+    _mm256_loadu_ps,
+    _mm256_storeu_ps,
+    _mm256_add_ps,
+    _mm256_sub_ps,
+    _mm256_mul_ps,
+    _mm256_cmp_ps,
+    _mm256_blendv_ps,
+    _mm256_and_ps,
+    _mm256_andnot_ps,
+    _mm256_movemask_ps
+);
+
+/// `Complex::escape_count` for the plain mandelbrot/julia iteration on 4 points at a time, or
+/// `None` if the cpu doesn't support AVX
+#[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
+pub fn escape_counts_f64(
+    points: &[(Complex<f64>, Complex<f64>)],
+    bound: f64,
+    max_iters: usize,
+    interior_checks: bool,
+) -> Option<Vec<Escape<f64>>> {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx") {
+        // safe as the cpu has just been checked for AVX
+        return Some(unsafe { avx_escape_counts_f64(points, bound, max_iters, interior_checks) });
+    }
+    None
+}
+
+/// as `escape_counts_f64`, with 8 points at a time
+#[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
+pub fn escape_counts_f32(
+    points: &[(Complex<f32>, Complex<f32>)],
+    bound: f64,
+    max_iters: usize,
+    interior_checks: bool,
+) -> Option<Vec<Escape<f32>>> {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx") {
+        // safe as the cpu has just been checked for AVX
+        return Some(unsafe { avx_escape_counts_f32(points, bound, max_iters, interior_checks) });
+    }
+    None
+}
+
+#[cfg(all(test, target_arch = "x86_64"))]
+mod tests {
+    use super::*;
+
+    /// c over the whole set on a grid which lands exactly on the cusp at 1/4, the pinch at -3/4
+    /// and the tip at -2, plus points just either side of them where rounding decides whether
+    /// an orbit escapes, and julia points with z_0 starting on and outside the bailout. the
+    /// count isn't a multiple of either vector width, so the last chunk is padded.
+    fn points<T: Real>() -> Vec<(Complex<T>, Complex<T>)> {
+        let complex = |re: f64, im: f64| Complex::<T>::from_f64(Complex::new(re, im));
+        let mut points: Vec<_> = (0..=112)
+            .flat_map(|x| (0..=96).map(move |y| (-2.5 + x as f64 / 32., -1.5 + y as f64 / 32.)))
+            .map(|(re, im)| (complex(re, im), Complex::id()))
+            .collect();
+        for (re, im) in [(0.25, 0.), (-0.75, 0.), (-2., 0.), (-0.75, 0.1)] {
+            for offset in [1e-3, 1e-6, 1e-9] {
+                for (d_re, d_im) in [(offset, 0.), (-offset, 0.), (0., offset), (0., -offset)] {
+                    points.push((complex(re + d_re, im + d_im), Complex::id()));
+                }
+            }
+        }
+        for (re, im) in [(2., 0.), (0., 2.), (1.5, 1.5), (0.3, 0.5)] {
+            points.push((complex(-0.8, 0.156), complex(re, im)));
+        }
+        points
+    }
+
+    fn assert_matches_scalar<T: Real + PartialEq + std::fmt::Debug>() {
+        let points = points::<T>();
+        for bound in [2., 1e6] {
+            for interior_checks in [false, true] {
+                let vectorised = T::mandelbrot_escape_counts(&points, bound, 500, interior_checks)
+                    .expect("checked for AVX");
+                for (&(c, z_0), escape) in points.iter().zip(vectorised) {
+                    let scalar = c.escape_count(
+                        z_0,
+                        Fractal::Mandelbrot,
+                        Exponent::Integer(2),
+                        bound,
+                        500,
+                        interior_checks,
+                    );
+                    assert_eq!(
+                        (escape.iters, escape.z.re, escape.z.im),
+                        (scalar.iters, scalar.z.re, scalar.z.im),
+                        "c = {:?}, z_0 = {:?}, bound {}, interior checks {}",
+                        c,
+                        z_0,
+                        bound,
+                        interior_checks
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn avx_matches_scalar_f64() {
+        if !is_x86_feature_detected!("avx") {
+            return;
+        }
+        assert_matches_scalar::<f64>();
+    }
+
+    #[test]
+    fn avx_matches_scalar_f32() {
+        if !is_x86_feature_detected!("avx") {
+            return;
+        }
+        assert_matches_scalar::<f32>();
+    }
+}
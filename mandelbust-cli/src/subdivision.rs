@@ -0,0 +1,144 @@
+use crate::field::Field;
+use crate::mandelbrot::{Complex, Escape, Exponent, Fractal, OrbitAverage};
+use crate::numeric::Real;
+use crate::transform::Transform;
+use crate::view::PixelMap;
+use rayon::prelude::*;
+use std::sync::OnceLock;
+
+/// rectangles with a side this short or shorter are iterated pixel by pixel
+const MIN_SIDE: usize = 4;
+
+/// an inclusive range of pixels, borders included
+#[derive(Clone, Copy, Debug)]
+struct Rect {
+    x0: usize,
+    y0: usize,
+    x1: usize,
+    y1: usize,
+}
+
+impl Rect {
+    fn border(self) -> impl Iterator<Item = (usize, usize)> {
+        let horizontal = (self.x0..=self.x1).flat_map(move |x| [(x, self.y0), (x, self.y1)]);
+        let vertical = (self.y0 + 1..self.y1).flat_map(move |y| [(self.x0, y), (self.x1, y)]);
+        horizontal.chain(vertical)
+    }
+
+    fn interior(self) -> impl Iterator<Item = (usize, usize)> {
+        (self.x0 + 1..self.x1).flat_map(move |x| (self.y0 + 1..self.y1).map(move |y| (x, y)))
+    }
+
+    /// halves the longer side, with both halves sharing the dividing line
+    fn split(self) -> (Rect, Rect) {
+        if self.x1 - self.x0 >= self.y1 - self.y0 {
+            let mid = (self.x0 + self.x1) / 2;
+            (Rect { x1: mid, ..self }, Rect { x0: mid, ..self })
+        } else {
+            let mid = (self.y0 + self.y1) / 2;
+            (Rect { y1: mid, ..self }, Rect { y0: mid, ..self })
+        }
+    }
+}
+
+/// a drop-in replacement for `generate_escape_counts` using mariani-silver subdivision: the
+/// border of a rectangle is iterated, and if every border pixel comes out the same the inside is
+/// filled with that value, otherwise the rectangle is split in two and each half is tried again.
+/// this relies on the set being connected, so tiny features poking into a rectangle without
+/// touching its border can be lost.
+///
+/// borders are compared after `post_fn`, so for count-based colouring a rectangle is filled when
+/// its border shares one escape count, while smooth colouring only fills regions of interior points.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_subdivided_escape_counts<T, F, R>(
+    centre: Complex,
+    julia: Option<Complex>,
+    transform: &Transform,
+    fractal: Fractal,
+    exponent: Exponent,
+    pixel_map: &PixelMap,
+    width: usize,
+    height: usize,
+    max_iters: usize,
+    bailout: f64,
+    interior_checks: bool,
+    average: Option<OrbitAverage>,
+    derivative: bool,
+    post_fn: F,
+) -> Field<R>
+where
+    T: Real,
+    R: Copy + PartialEq + Send + Sync,
+    F: Fn(Escape) -> R + std::marker::Sync,
+{
+    let centre = Complex::<T>::from_f64(centre);
+    let julia = julia.map(Complex::<T>::from_f64);
+    let pixels: Vec<OnceLock<R>> = (0..width * height).map(|_| OnceLock::new()).collect();
+    let pixel = |(x, y): (usize, usize)| {
+        *pixels[y * width + x].get_or_init(|| {
+            let point =
+                transform.apply(centre + Complex::from_f64(pixel_map.offset(x as f64, y as f64)));
+            let (c, z_0) = match julia {
+                Some(c) => (c, point),
+                None => (point, Complex::id()),
+            };
+            let escape = c.escape_count_tracked(
+                z_0,
+                julia.is_some(),
+                fractal,
+                exponent,
+                bailout,
+                max_iters,
+                interior_checks,
+                average,
+                derivative,
+            );
+            post_fn(escape.to_f64())
+        })
+    };
+
+    if width > 0 && height > 0 {
+        subdivide(
+            Rect {
+                x0: 0,
+                y0: 0,
+                x1: width - 1,
+                y1: height - 1,
+            },
+            &pixel,
+            &|(x, y), value| {
+                pixels[y * width + x].get_or_init(|| value);
+            },
+        );
+    }
+
+    let values = pixels
+        .into_par_iter()
+        .map(|value| {
+            value
+                .into_inner()
+                .expect("every pixel is iterated or filled")
+        })
+        .collect();
+    Field::new(width, height, values)
+}
+
+fn subdivide<R, P, S>(rect: Rect, pixel: &P, fill: &S)
+where
+    R: Copy + PartialEq,
+    P: Fn((usize, usize)) -> R + Sync,
+    S: Fn((usize, usize), R) + Sync,
+{
+    let first = pixel((rect.x0, rect.y0));
+    if rect.border().all(|p| pixel(p) == first) {
+        rect.interior().for_each(|p| fill(p, first));
+    } else if rect.x1 - rect.x0 <= MIN_SIDE || rect.y1 - rect.y0 <= MIN_SIDE {
+        // the border check stops at the first mismatch, so the border may not be done either
+        rect.border().chain(rect.interior()).for_each(|p| {
+            pixel(p);
+        });
+    } else {
+        let (a, b) = rect.split();
+        rayon::join(|| subdivide(a, pixel, fill), || subdivide(b, pixel, fill));
+    }
+}
@@ -0,0 +1,129 @@
+use crate::mandelbrot::Complex;
+use crate::numeric::Real;
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// radius of the circle `TransformKind::Stereographic` wraps round the sphere's equator, which
+/// holds the whole mandelbrot set
+const SPHERE_RADIUS: f64 = 2.;
+
+/// where points sent to infinity go instead: far enough out to escape straight away, but still
+/// finite in an `f32`, so they colour like any other quickly escaping point
+const INFINITY_STAND_IN: f64 = 1e18;
+
+/// ways of moving each pixel's point before it's iterated, to render variants of a fractal
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum TransformKind {
+    /// iterate points where they are
+    #[default]
+    None,
+    /// 1 / p, turning the plane inside out about the unit circle, as in the inverted
+    /// mandelbrot set
+    Invert,
+    /// (a p + b) / (c p + d), with a, b, c and d from --mobius
+    Mobius,
+    /// draw the plane on a globe, as the riemann sphere seen from straight on. the globe has
+    /// radius 1 in the view, and pixels off it are treated as infinity.
+    Stereographic,
+}
+
+impl TransformKind {
+    pub const ALL: [TransformKind; 4] = [
+        TransformKind::None,
+        TransformKind::Invert,
+        TransformKind::Mobius,
+        TransformKind::Stereographic,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Transform {
+    pub kind: TransformKind,
+    /// a, b, c and d of a möbius transform
+    pub coefficients: [Complex; 4],
+    /// degrees the globe of a stereographic transform is tipped back about the real axis; at
+    /// 180 the point at infinity faces the viewer
+    pub tilt: f64,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            kind: TransformKind::None,
+            coefficients: [
+                Complex::new(1., 0.),
+                Complex::id(),
+                Complex::id(),
+                Complex::new(1., 0.),
+            ],
+            tilt: 0.,
+        }
+    }
+}
+
+impl Transform {
+    /// the möbius transform with coefficients a, b, c and d, which has to be invertible
+    pub fn mobius(coefficients: &[Complex]) -> Result<Self> {
+        let &[a, b, c, d] = coefficients else {
+            return Err(anyhow!(
+                "a möbius transform needs four coefficients, a,b,c,d"
+            ));
+        };
+        let determinant = a * d - b * c;
+        if determinant.abs_value_sq() == 0. {
+            return Err(anyhow!(
+                "a möbius transform needs ad - bc to be non-zero, or it squashes the plane to a point"
+            ));
+        }
+        Ok(Self {
+            kind: TransformKind::Mobius,
+            coefficients: [a, b, c, d],
+            ..Self::default()
+        })
+    }
+
+    pub fn is_identity(&self) -> bool {
+        self.kind == TransformKind::None
+    }
+
+    /// where the point `p` is moved to
+    pub fn apply<T: Real>(&self, p: Complex<T>) -> Complex<T> {
+        let zero = T::from_f64(0.);
+        let infinity = Complex::new(T::from_f64(INFINITY_STAND_IN), zero);
+        match self.kind {
+            TransformKind::None => p,
+            TransformKind::Invert if p.abs_value_sq() == zero => infinity,
+            TransformKind::Invert => p.inverse(),
+            TransformKind::Mobius => {
+                let [a, b, c, d] = self.coefficients.map(Complex::<T>::from_f64);
+                let denominator = c * p + d;
+                if denominator.abs_value_sq() == zero {
+                    infinity
+                } else {
+                    (a * p + b) / denominator
+                }
+            }
+            TransformKind::Stereographic => Complex::from_f64(self.stereographic(p.to_f64())),
+        }
+    }
+
+    /// the point on the plane under `p` on the globe. evaluated using `f64`s, so this gains
+    /// nothing from a more precise `T`
+    fn stereographic(&self, p: Complex) -> Complex {
+        let off_globe = Complex::new(INFINITY_STAND_IN, 0.);
+        let distance_sq = p.abs_value_sq();
+        if distance_sq > 1. {
+            return off_globe;
+        }
+        // how far the globe's surface under `p` comes out towards the viewer
+        let depth = (1. - distance_sq).sqrt();
+        let (sin, cos) = self.tilt.to_radians().sin_cos();
+        let (up, towards) = (p.im * cos - depth * sin, p.im * sin + depth * cos);
+        // projected from the pole facing away from the viewer when the globe isn't tipped
+        if 1. + towards <= f64::EPSILON {
+            return off_globe;
+        }
+        Complex::new(p.re, up).scale(SPHERE_RADIUS / (1. + towards))
+    }
+}
@@ -0,0 +1,147 @@
+use crate::field::Field;
+use crate::mandelbrot::{map_pixels, Complex, Escape, Exponent, Fractal};
+use crate::numeric::Real;
+use crate::transform::Transform;
+use crate::view::PixelMap;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// shapes an orbit can be caught in, for colouring by how closely orbits pass them
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum TrapShape {
+    /// the trap's centre
+    #[default]
+    Point,
+    /// the horizontal line through the trap's centre
+    Line,
+    /// the horizontal and vertical lines through the trap's centre
+    Cross,
+    /// the circle around the trap's centre with the trap's size as its radius
+    Circle,
+}
+
+impl TrapShape {
+    pub const ALL: [TrapShape; 4] = [
+        TrapShape::Point,
+        TrapShape::Line,
+        TrapShape::Cross,
+        TrapShape::Circle,
+    ];
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct OrbitTrap {
+    pub shape: TrapShape,
+    pub centre: Complex,
+    /// how far from the trap an orbit can pass and still be coloured by it
+    pub size: f64,
+}
+
+impl OrbitTrap {
+    pub fn distance(&self, z: Complex) -> f64 {
+        let offset = z - self.centre;
+        match self.shape {
+            TrapShape::Point => offset.abs_value_sq().sqrt(),
+            TrapShape::Line => offset.im.abs(),
+            TrapShape::Cross => offset.re.abs().min(offset.im.abs()),
+            TrapShape::Circle => (offset.abs_value_sq().sqrt() - self.size).abs(),
+        }
+    }
+
+    /// the closest the orbit of `c` from `z_0` comes to the trap, or `None` if it never escapes,
+    /// along with where it escaped. with `derivative`, the escape carries it as
+    /// `escape_count_with_derivative`'s does for `julia`, which only works for z -> z^d + c
+    #[allow(clippy::too_many_arguments)]
+    pub fn closest_approach<T: Real>(
+        &self,
+        c: Complex<T>,
+        z_0: Complex<T>,
+        julia: bool,
+        fractal: Fractal,
+        exponent: Exponent,
+        bound: f64,
+        max_iters: usize,
+        interior_checks: bool,
+        derivative: bool,
+    ) -> (Option<f64>, Escape<T>) {
+        let mut closest = f64::INFINITY;
+        let observe = |z: Complex<T>| closest = closest.min(self.distance(z.to_f64()));
+        let escape = if derivative {
+            c.escape_count_observed_with_derivative(
+                z_0,
+                julia,
+                exponent,
+                bound,
+                max_iters,
+                interior_checks,
+                observe,
+            )
+        } else {
+            c.escape_count_observed(
+                z_0,
+                fractal,
+                exponent,
+                bound,
+                max_iters,
+                interior_checks,
+                observe,
+            )
+        };
+        ((escape.iters < max_iters).then_some(closest), escape)
+    }
+
+    /// the closest an orbit came to the trap, relative to its size: 0 for orbits that hit
+    /// it, up to 1 for orbits that never came within `size`
+    pub fn hue(&self, distance: f64) -> f64 {
+        (distance / self.size).min(1.)
+    }
+}
+
+/// the closest each pixel's orbit comes to `trap`, or `None` for pixels which never escape,
+/// boiled down by `post_fn` along with the pixel's `Escape`, which carries the derivative with
+/// `derivative`
+#[allow(clippy::too_many_arguments)]
+pub fn generate_trap_distances<T, F, R>(
+    centre: Complex,
+    julia: Option<Complex>,
+    transform: &Transform,
+    fractal: Fractal,
+    exponent: Exponent,
+    trap: &OrbitTrap,
+    pixel_map: &PixelMap,
+    width: usize,
+    height: usize,
+    max_iters: usize,
+    bailout: f64,
+    interior_checks: bool,
+    derivative: bool,
+    post_fn: F,
+) -> Field<R>
+where
+    T: Real,
+    R: Send,
+    F: Fn(Option<f64>, Escape) -> R + Sync,
+{
+    map_pixels(
+        centre,
+        julia,
+        transform,
+        pixel_map,
+        width,
+        height,
+        |c: Complex<T>, z_0| {
+            let (distance, escape) = trap.closest_approach(
+                c,
+                z_0,
+                julia.is_some(),
+                fractal,
+                exponent,
+                bailout,
+                max_iters,
+                interior_checks,
+                derivative,
+            );
+            post_fn(distance, escape.to_f64())
+        },
+    )
+}
@@ -0,0 +1,141 @@
+use crate::field::Tile;
+use crate::mandelbrot::Complex;
+use crate::zoom::Zoom;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// which way up a view is drawn. with the default, positive real is to the right and positive
+/// imaginary is up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Orientation {
+    /// degrees the view is turned anticlockwise about its centre
+    pub rotation: f64,
+    /// mirror the view left to right, after turning it
+    pub flip_horizontal: bool,
+    /// mirror the view top to bottom, after turning it
+    pub flip_vertical: bool,
+}
+
+impl Orientation {
+    /// the direction in the plane, in degrees anticlockwise from +re, which points `degrees`
+    /// anticlockwise from the right of the image once the view is turned and mirrored
+    pub fn plane_angle(&self, degrees: f64) -> f64 {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let right = if self.flip_horizontal { -cos } else { cos };
+        let up = if self.flip_vertical { -sin } else { sin };
+        self.rotation + up.atan2(right).to_degrees()
+    }
+}
+
+/// maps the pixels of an image to offsets in the complex plane from the centre of its view.
+/// pixel (x, y), counting down from the top left, is sampled at
+/// `origin + x * step_x + y * step_y`, so any turn, mirroring or shift of the grid is just
+/// a different map.
+#[derive(Clone, Copy, Debug)]
+pub struct PixelMap {
+    /// the top left corner of the first pixel
+    pub origin: Complex,
+    /// the move from one pixel to the next one right
+    pub step_x: Complex,
+    /// the move from one pixel to the next one down
+    pub step_y: Complex,
+}
+
+impl PixelMap {
+    /// the map of a `width` by `height` image at `zoom`, turned to `orientation`. the shorter
+    /// side of the image spans 18 / `zoom`, and the longer side as much more as the aspect
+    /// ratio asks, so pixels stay square.
+    pub fn new(zoom: f64, orientation: Orientation, width: usize, height: usize) -> Self {
+        let (half_width, half_height) = if width >= height {
+            (9. * width as f64 / height as f64, 9.)
+        } else {
+            (9., 9. * height as f64 / width as f64)
+        };
+        let (half_width, half_height) = (half_width / zoom, half_height / zoom);
+        let spacing = 2. * half_width / width as f64;
+        let (right, down) = (
+            if orientation.flip_horizontal { -1. } else { 1. },
+            if orientation.flip_vertical { -1. } else { 1. },
+        );
+        let (sin, cos) = orientation.rotation.to_radians().sin_cos();
+        let turn = Complex::new(cos, sin);
+        Self {
+            origin: turn * Complex::new(-right * half_width, down * half_height),
+            step_x: turn * Complex::new(right * spacing, 0.),
+            step_y: turn * Complex::new(0., -down * spacing),
+        }
+    }
+
+    /// the offset of the point `x` pixels right and `y` pixels down from the top left corner,
+    /// where whole numbers land on the corners of pixels
+    pub fn offset(&self, x: f64, y: f64) -> Complex {
+        self.origin + self.step_x.scale(x) + self.step_y.scale(y)
+    }
+
+    /// the pixel coordinates `offset` lands on, undoing `offset`
+    pub fn pixel(&self, offset: Complex) -> (f64, f64) {
+        let relative = offset - self.origin;
+        let determinant = self.step_x.re * self.step_y.im - self.step_x.im * self.step_y.re;
+        (
+            (relative.re * self.step_y.im - relative.im * self.step_y.re) / determinant,
+            (self.step_x.re * relative.im - self.step_x.im * relative.re) / determinant,
+        )
+    }
+
+    /// the distance between neighbouring pixels. `hypot` doesn't square the step, so it
+    /// doesn't underflow to 0 at the zooms where steps are far below `1e-154`.
+    pub fn spacing(&self) -> f64 {
+        self.step_x.re.hypot(self.step_x.im)
+    }
+
+    /// the same grid with every pixel sampled `x` pixels right and `y` pixels down
+    pub fn shifted(&self, x: f64, y: f64) -> Self {
+        Self {
+            origin: self.offset(x, y),
+            ..*self
+        }
+    }
+
+    /// the map of just the pixels of `tile`
+    pub fn tile(&self, tile: Tile) -> Self {
+        self.shifted(tile.x as f64, tile.y as f64)
+    }
+
+    /// how far from the centre, along either axis of the plane, any corner of a `width` by
+    /// `height` image reaches
+    pub fn extent(&self, width: usize, height: usize) -> f64 {
+        [(0, 0), (width, 0), (0, height), (width, height)]
+            .into_iter()
+            .map(|(x, y)| self.offset(x as f64, y as f64))
+            .map(|corner| corner.re.abs().max(corner.im.abs()))
+            .fold(0., f64::max)
+    }
+}
+
+/// the centre and zoom of the view of a `width` by `height` image which just fits the box with
+/// opposite corners `a` and `b`, before it's turned. the box is centred, and has room either side
+/// of it along one axis if its aspect ratio isn't the image's.
+pub fn fit_corners(a: Complex, b: Complex, width: usize, height: usize) -> Result<(Complex, Zoom)> {
+    let centre = (a + b).scale(0.5);
+    let shorter_side = width.min(height) as f64;
+    // the span of the image's shorter side which fits the box along both axes
+    let span = ((a.re - b.re).abs() * shorter_side / width as f64)
+        .max((a.im - b.im).abs() * shorter_side / height as f64);
+    Ok((centre, Zoom::try_new(18. / span)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spacing_survives_deep_zooms() {
+        let orientation = Orientation {
+            rotation: 30.,
+            ..Orientation::default()
+        };
+        let spacing = PixelMap::new(1e300, orientation, 320, 180).spacing();
+        let expected = 18. / 1e300 / 180.;
+        assert!((spacing - expected).abs() <= expected * 1e-12);
+    }
+}
@@ -0,0 +1,213 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// how far a view is magnified. the view's shorter side spans 18 / the zoom.
+///
+/// it's kept as a mantissa between 1 and 2 and a power of 2, the same as a float but with a
+/// much wider exponent, so zooms far past `f64::MAX` can be stored and scaled without
+/// overflowing, and zooms below 1 are fine too. every `f64` zoom is represented exactly.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Zoom {
+    mantissa: f64,
+    exponent: i64,
+}
+
+impl Default for Zoom {
+    fn default() -> Self {
+        Self::new(8.)
+    }
+}
+
+impl Zoom {
+    /// a zoom magnifying by `scale`, which has to be a positive, normal float
+    pub fn new(scale: f64) -> Self {
+        assert!(
+            scale.is_normal() && scale > 0.,
+            "zoom must be positive and finite"
+        );
+        Self::normalised(scale, 0)
+    }
+
+    /// `new`, but with an error rather than a panic for scales which aren't positive and normal
+    pub fn try_new(scale: f64) -> Result<Self> {
+        if scale.is_normal() && scale > 0. {
+            Ok(Self::new(scale))
+        } else {
+            Err(anyhow!("a zoom of {} can't be rendered", scale))
+        }
+    }
+
+    /// the zoom at which the shorter side of the image reaches `radius` either side of the
+    /// centre
+    pub fn from_radius(radius: f64) -> Result<Self> {
+        Self::try_new(9. / radius)
+    }
+
+    /// the zoom at which neighbouring pixels of a `width` by `height` image are `size` apart
+    pub fn from_pixel_size(size: f64, width: usize, height: usize) -> Result<Self> {
+        Self::try_new(18. / (size * width.min(height) as f64))
+    }
+
+    /// a zoom magnifying by 2 to the `log2`
+    pub fn from_log2(log2: f64) -> Self {
+        let exponent = log2.floor();
+        Self::normalised((log2 - exponent).exp2(), exponent as i64)
+    }
+
+    fn normalised(mantissa: f64, exponent: i64) -> Self {
+        // scaling by a power of 2 is exact, so this only moves bits between the two parts
+        let shift = mantissa.log2().floor() as i32;
+        let mut mantissa = mantissa * 2f64.powi(-shift);
+        let mut exponent = exponent + shift as i64;
+        // the log can be a little off right next to a power of 2
+        while mantissa >= 2. {
+            mantissa /= 2.;
+            exponent += 1;
+        }
+        while mantissa < 1. {
+            mantissa *= 2.;
+            exponent -= 1;
+        }
+        Self { mantissa, exponent }
+    }
+
+    /// the magnification as a float, which is infinite or 0 beyond the range of `f64`
+    pub fn scale(self) -> f64 {
+        let exponent = self.exponent.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        // split up so that the mantissa isn't lost when the power alone would underflow
+        self.mantissa * 2f64.powi(exponent / 2) * 2f64.powi(exponent - exponent / 2)
+    }
+
+    /// the magnification as a float, or an error if it's too deep or shallow for one
+    pub fn to_scale(self) -> Result<f64> {
+        let scale = self.scale();
+        if scale.is_normal() {
+            Ok(scale)
+        } else {
+            Err(anyhow!("zoom {} is beyond what can be rendered", self))
+        }
+    }
+
+    pub fn log2(self) -> f64 {
+        self.mantissa.log2() + self.exponent as f64
+    }
+
+    /// how far the shorter side of the image reaches either side of the centre, the inverse of
+    /// `from_radius`
+    pub fn radius(self) -> f64 {
+        Self::normalised(9. / self.mantissa, -self.exponent).scale()
+    }
+
+    /// the distance between neighbouring pixels of a `width` by `height` image, the inverse of
+    /// `from_pixel_size`
+    pub fn pixel_size(self, width: usize, height: usize) -> f64 {
+        Self::normalised(
+            18. / self.mantissa / width.min(height) as f64,
+            -self.exponent,
+        )
+        .scale()
+    }
+
+    /// this zoom magnified `factor` times more, zooming out for factors below 1
+    pub fn times(self, factor: f64) -> Self {
+        assert!(
+            factor.is_normal() && factor > 0.,
+            "zoom factor must be positive and finite"
+        );
+        Self::normalised(self.mantissa * factor, self.exponent)
+    }
+}
+
+/// prints the zoom as a plain number while it fits in an `f64`, and in scientific notation
+/// with a decimal exponent past that
+impl fmt::Display for Zoom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scale = self.scale();
+        if (1e-4..1e16).contains(&scale) {
+            write!(f, "{}", scale)
+        } else if scale.is_normal() {
+            write!(f, "{:e}", scale)
+        } else {
+            // the log loses the last few digits, so they aren't printed
+            let log10 = self.log2() * 2f64.log10();
+            let mut exponent = log10.floor();
+            let mut mantissa = (10f64.powf(log10 - exponent) * 1e10).round() / 1e10;
+            if mantissa >= 10. {
+                mantissa /= 10.;
+                exponent += 1.;
+            }
+            write!(f, "{}e{}", mantissa, exponent)
+        }
+    }
+}
+
+/// parses a magnification like `8`, `0.5` or `2.5e400`, or a power of 2 like `2^1500`. the
+/// decimal exponent can go beyond the range of `f64`.
+impl FromStr for Zoom {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let invalid = || anyhow!("couldn't parse {} as a zoom", s);
+        let zoom = if let Some(log2) = s.strip_prefix("2^") {
+            let log2: f64 = log2.parse().map_err(|_| invalid())?;
+            if !log2.is_finite() {
+                return Err(invalid());
+            }
+            Self::from_log2(log2)
+        } else {
+            let (mantissa, exponent) = match s.split_once(['e', 'E']) {
+                Some((mantissa, exponent)) => {
+                    (mantissa, exponent.parse::<i64>().map_err(|_| invalid())?)
+                }
+                None => (s, 0),
+            };
+            let mantissa: f64 = mantissa.parse().map_err(|_| invalid())?;
+            if !(mantissa.is_normal() && mantissa > 0.) {
+                return Err(anyhow!("zoom must be positive, not {}", s));
+            }
+            // exact for the exponents an f64 can hold, and as close as logs get past them
+            match format!("{}e{}", mantissa, exponent).parse::<f64>() {
+                Ok(scale) if scale.is_normal() => Self::new(scale),
+                _ => Self::from_log2(mantissa.log2() + exponent as f64 * 10f64.log2()),
+            }
+        };
+        Ok(zoom)
+    }
+}
+
+/// a zoom as it's written in a config: a number for any zoom an `f64` can hold, which is how
+/// every zoom was written before they could go further, or a string otherwise
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ZoomRepr {
+    Scale(f64),
+    Text(String),
+}
+
+impl Serialize for Zoom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let scale = self.scale();
+        if scale.is_normal() {
+            ZoomRepr::Scale(scale)
+        } else {
+            ZoomRepr::Text(self.to_string())
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Zoom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match ZoomRepr::deserialize(deserializer)? {
+            ZoomRepr::Scale(scale) if scale.is_normal() && scale > 0. => Ok(Self::new(scale)),
+            ZoomRepr::Scale(scale) => Err(serde::de::Error::custom(format!(
+                "zoom must be positive, not {}",
+                scale
+            ))),
+            ZoomRepr::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
@@ -1,62 +1,136 @@
-use image::{imageops::resize, DynamicImage, GenericImageView, ImageBuffer};
-use kdam::tqdm;
+use image::{
+    imageops::{crop_imm, resize},
+    Rgb, RgbImage,
+};
 use mandelbust_cli::{
     config::Configuration,
-    opts::{Cli, PlottingAlgorithm},
+    mandelbrot::{Complex, Equalisation},
+    opts::{Cli, PlottingAlgorithm, Precision, SamplePattern},
+    render::{ProgressBar, Renderer},
+    transform::TransformKind,
+    trap::TrapShape,
+    zoom::Zoom,
 };
 
+/// number of frames rendered in full; the frames between are zoomed in from them
+const FRAMES: usize = 30;
+/// samples averaged into each pixel of a rendered frame; each one costs as much as the frame
+const SAMPLES: usize = 1;
+/// degrees the view turns anticlockwise from one rendered frame to the next. the frames zoomed
+/// in from a render turn part of the way, so keep it to a few degrees or the render's corners
+/// show at their edges.
+const TURN_PER_FRAME: f64 = 0.;
+
 fn main() {
     let conf: Configuration = confy::load("mandelbust", "config").unwrap();
     let place = conf.get_named_point("circle").unwrap();
-    let mut zoom = 8.;
-    for i in tqdm!(0..30) {
+    let mut zoom = Zoom::default();
+    for i in 0..FRAMES {
         let conf = Cli {
             out_file: format!("out/{}.png", i),
             max_iters: 10000,
             bailout: 1e9,
             resolution: mandelbust_cli::opts::Resolution::High,
+            width: None,
+            height: None,
+            rotation: Some(i as f64 * TURN_PER_FRAME),
+            flip_horizontal: false,
+            flip_vertical: false,
+            transform: TransformKind::None,
+            mobius: vec![],
+            sphere_tilt: 0.,
             palette: "warm".into(),
             palette_repeats: 50,
             algorithm: PlottingAlgorithm::Smooth,
+            fractal: Some(place.fractal),
+            exponent: 2.,
+            deep_zoom: true,
+            precision: Precision::Auto,
+            no_interior_checks: false,
+            subdivide: false,
+            thickness: 1.,
+            trap: TrapShape::Point,
+            trap_centre: Complex::id(),
+            trap_size: 1.,
+            stripe_density: 5.,
+            hist_equalisation: Equalisation::Count,
+            hist_exclude_interior: false,
+            hist_blend: 1.,
+            lighting: None,
+            light_angle: 45.,
+            light_height: 1.,
+            samples: SAMPLES,
+            sample_pattern: SamplePattern::Grid,
+            adaptive: false,
+            edge_threshold: 32.,
+            raw_out: None,
+            no_image: false,
+            print_view: false,
             command: mandelbust_cli::opts::Commands::Centre {
-                x: place.point.re,
-                y: place.point.im,
-                zoom: zoom as usize,
+                x: place.point.re.clone(),
+                y: place.point.im.clone(),
+                zoom,
             },
         };
 
-        let hue_array = conf.get_hue_array().unwrap();
-        println!("done calculating");
-        let (width, height) = conf.resolution.to_dimensions();
-        let palette = conf.get_palette().unwrap();
-        let img = ImageBuffer::from_fn(width as u32, height as u32, |x, y| {
-            let frac = hue_array[x as usize][y as usize];
-            palette.value(frac)
-        });
-        let mut dyn_image = DynamicImage::ImageRgb8(img);
+        let bar = ProgressBar::new(format!("frame {}/{}", i + 1, FRAMES));
+        let renderer = Renderer::new().on_progress(|progress| bar.update(progress));
+        let (img, _) = conf.render(&renderer).unwrap();
+        bar.finish();
+        // frames come out at half the size rendered, in whatever shape that is
+        let (width, height) = img.dimensions();
         for j in 0..27 {
-            dyn_image = crop_image(dyn_image);
+            // turning the view anticlockwise turns what's in it clockwise
+            let turned = turn(&img, -TURN_PER_FRAME * (j + 1) as f64 / 27.);
+            // each sub-frame is zoomed in by one more crop than the last
+            let (mut x, mut y, mut crop_width, mut crop_height) = (0, 0, width, height);
+            for _ in 0..=j {
+                let (dx, dy, next_width, next_height) = crop_box(crop_width, crop_height);
+                (x, y, crop_width, crop_height) = (x + dx, y + dy, next_width, next_height);
+            }
             let scaled = resize(
-                &dyn_image,
-                960,
-                540,
+                &*crop_imm(&turned, x, y, crop_width, crop_height),
+                width / 2,
+                height / 2,
                 image::imageops::FilterType::CatmullRom,
             );
             scaled.save(format!("out/{}.png", i * 27 + j)).unwrap();
         }
-        zoom *= 26.7027;
+        zoom = zoom.times(26.7027);
     }
 }
 
-fn crop_image(mut image: DynamicImage) -> DynamicImage {
-    let (width, height) = image.dimensions();
+/// the middle 90% of an image `width` by `height`, as its left, top, width and height
+fn crop_box(width: u32, height: u32) -> (u32, u32, u32, u32) {
     let x_start = width / 20; // Start 5% in from the left edge
     let y_start = height / 20; // Start 5% in from the top edge
 
-    image.crop(
+    (
         x_start,
         y_start,
         (width as f64 * 0.90) as u32,
         (height as f64 * 0.90) as u32,
     )
 }
+
+/// `image` turned anticlockwise by `degrees` about its middle, with black where the corners turn
+/// in from outside it
+fn turn(image: &RgbImage, degrees: f64) -> RgbImage {
+    if degrees == 0. {
+        return image.clone();
+    }
+    let (width, height) = image.dimensions();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (mid_x, mid_y) = (width as f64 / 2., height as f64 / 2.);
+    RgbImage::from_fn(width, height, |x, y| {
+        let (dx, dy) = (x as f64 + 0.5 - mid_x, y as f64 + 0.5 - mid_y);
+        // y counts down the image, so this is the clockwise turn taking each pixel back to
+        // where it's turned from
+        let (source_x, source_y) = (mid_x + dx * cos - dy * sin, mid_y + dx * sin + dy * cos);
+        if (0. ..width as f64).contains(&source_x) && (0. ..height as f64).contains(&source_y) {
+            *image.get_pixel(source_x as u32, source_y as u32)
+        } else {
+            Rgb([0, 0, 0])
+        }
+    })
+}
@@ -1,13 +1,27 @@
 use anyhow::Result;
-use eframe::egui::{DragValue, Image, Key, Sense, Slider, Ui};
+use eframe::egui::{
+    Color32, DragValue, Image, Key, PointerButton, ProgressBar, Sense, Slider, Ui, Vec2,
+};
 use eframe::emath::Align;
 use eframe::epaint::ColorImage;
 use eframe::{egui, run_native};
-use image::{ImageBuffer, Rgb};
+use image::{ImageBuffer, Rgb, RgbImage};
 use mandelbust_cli::config::{Configuration, NamedPoint};
-use mandelbust_cli::mandelbrot::Complex;
-use mandelbust_cli::opts::{get_intervals, Cli, Commands, PlottingAlgorithm, Resolution};
+use mandelbust_cli::coordinate::Point;
+use mandelbust_cli::lighting::LightingModel;
+use mandelbust_cli::mandelbrot::{Complex, Equalisation, Fractal};
+use mandelbust_cli::opts::{
+    Cli, Commands, PlottingAlgorithm, Precision, Resolution, SamplePattern,
+};
 use mandelbust_cli::palette::{ColorPalette, ConfigRGB};
+use mandelbust_cli::render::{CancelToken, Renderer};
+use mandelbust_cli::transform::TransformKind;
+use mandelbust_cli::trap::TrapShape;
+use mandelbust_cli::view::{Orientation, PixelMap};
+use mandelbust_cli::zoom::Zoom;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
 
 pub fn main() -> Result<(), eframe::Error> {
     run_native(
@@ -17,16 +31,53 @@ pub fn main() -> Result<(), eframe::Error> {
     )
 }
 
+/// a render running on a background thread
+struct RenderJob {
+    cancel: CancelToken,
+    /// share of the render done, between 0 and 1
+    progress: Arc<Mutex<f64>>,
+    result: Receiver<Result<RgbImage>>,
+    preview: Arc<Mutex<Preview>>,
+}
+
+/// the first pass of a render as it comes in, with each tile coloured roughly as it finishes
+struct Preview {
+    image: RgbImage,
+    /// whether tiles have finished since the image was last shown
+    changed: bool,
+}
+
 pub struct App {
     config: Configuration,
-    centre: Complex,
-    zoom: f64,
+    centre: Point,
+    zoom: Zoom,
+    orientation: Orientation,
+    transform: TransformKind,
+    /// degrees the globe is tipped back by the stereographic transform
+    sphere_tilt: f64,
     zoom_multiplier: f32,
     palette: String,
     landmark: String,
     image_texture: Option<egui::TextureHandle>,
     image: Option<ImageBuffer<Rgb<u8>, Vec<u8>>>,
+    job: Option<RenderJob>,
+    /// why the last render failed, shown in place of progress until one succeeds
+    error: Option<String>,
     iterations: usize,
+    fractal: Fractal,
+    exponent: f64,
+    deep_zoom: bool,
+    julia: Option<Complex>,
+    trap: Option<TrapShape>,
+    trap_centre: Complex,
+    trap_size: f64,
+    lighting: Option<LightingModel>,
+    light_angle: f64,
+    light_height: f64,
+    export_sample_side: usize,
+    /// size of the rendered image in pixels
+    width: usize,
+    height: usize,
     palette_cycles: usize,
     new_landmark_name: String,
     palette_editor: Vec<([u8; 3], f64)>,
@@ -44,14 +95,32 @@ impl Default for App {
             .collect();
         Self {
             config: conf.clone(),
-            centre: Complex::id(),
-            zoom: 8.,
+            centre: Point::default(),
+            zoom: Zoom::default(),
+            orientation: Orientation::default(),
+            transform: TransformKind::None,
+            sphere_tilt: 0.,
             zoom_multiplier: 2.,
             palette: "electric".into(),
             landmark: "".into(),
             image_texture: None,
             image: None,
+            job: None,
+            error: None,
             iterations: 5000,
+            fractal: Fractal::Mandelbrot,
+            exponent: 2.,
+            deep_zoom: false,
+            julia: None,
+            trap: None,
+            trap_centre: Complex::id(),
+            trap_size: 1.,
+            lighting: None,
+            light_angle: 45.,
+            light_height: 1.,
+            export_sample_side: 2,
+            width: 960,
+            height: 540,
             palette_cycles: 1,
             new_landmark_name: "".into(),
             palette_editor,
@@ -61,6 +130,24 @@ impl Default for App {
 
 impl eframe::App for App {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        match self.job.as_ref().map(|job| job.result.try_recv()) {
+            Some(Ok(result)) => {
+                // a failed render leaves the last image up, so the view can be put back
+                match result {
+                    Ok(image) => {
+                        self.image = Some(image);
+                        self.error = None;
+                    }
+                    Err(error) => self.error = Some(error.to_string()),
+                }
+                self.job = None;
+            }
+            // keep redrawing so the progress bar moves
+            Some(Err(TryRecvError::Empty)) => ctx.request_repaint(),
+            Some(Err(TryRecvError::Disconnected)) => self.job = None,
+            None => {}
+        }
+
         egui::TopBottomPanel::top("heading")
             .resizable(false)
             .show(ctx, |ui| {
@@ -78,24 +165,125 @@ impl eframe::App for App {
 }
 
 impl App {
-    fn refresh_image(&mut self) -> Result<()> {
-        let args = Cli {
+    /// starts rendering the current view in the background, cancelling any render that's
+    /// already running, so the window stays responsive while it works
+    fn refresh_image(&mut self) {
+        if let Some(job) = self.job.take() {
+            job.cancel.cancel();
+        }
+        let args = self.render_args(1);
+        let palette = self.palette();
+        let cancel = CancelToken::new();
+        let progress = Arc::new(Mutex::new(0.));
+        let (sender, result) = mpsc::channel();
+        let preview = Arc::new(Mutex::new(Preview {
+            image: RgbImage::new(self.width as u32, self.height as u32),
+            changed: false,
+        }));
+        let (token, fraction, canvas) = (cancel.clone(), progress.clone(), preview.clone());
+        thread::spawn(move || {
+            let renderer = Renderer::new()
+                .cancel_token(token)
+                .on_progress(|progress| *fraction.lock().unwrap() = progress.fraction())
+                .on_tile(|done| {
+                    if done.pass != 0 {
+                        return;
+                    }
+                    let colours: Vec<_> = done
+                        .values
+                        .iter()
+                        .map(|&value| palette.value(args.preview_hue(value)))
+                        .collect();
+                    let mut canvas = canvas.lock().unwrap();
+                    for (i, colour) in colours.into_iter().enumerate() {
+                        let (x, y) = (i % done.tile.width, i / done.tile.width);
+                        canvas.image.put_pixel(
+                            (done.tile.x + x) as u32,
+                            (done.tile.y + y) as u32,
+                            colour,
+                        );
+                    }
+                    canvas.changed = true;
+                });
+            let image = args
+                .get_image_with_palette(&palette, &renderer)
+                .map(|(image, _)| image);
+            // nobody's listening if the render was cancelled, and then the image isn't wanted
+            let _ = sender.send(image);
+        });
+        self.job = Some(RenderJob {
+            cancel,
+            progress,
+            result,
+            preview,
+        });
+    }
+
+    /// the options to render the current view with, averaging `samples` samples per pixel
+    fn render_args(&self, samples: usize) -> Cli {
+        Cli {
             out_file: "".to_string(),
             max_iters: self.iterations,
             bailout: 1e9,
             resolution: Resolution::Med,
+            width: Some(self.width),
+            height: Some(self.height),
+            rotation: Some(self.orientation.rotation),
+            flip_horizontal: self.orientation.flip_horizontal,
+            flip_vertical: self.orientation.flip_vertical,
+            transform: self.transform,
+            mobius: vec![],
+            sphere_tilt: self.sphere_tilt,
             palette: self.palette.clone(),
-            algorithm: PlottingAlgorithm::SmoothHistogram,
-            command: Commands::Centre {
-                x: self.centre.re,
-                y: self.centre.im,
-                zoom: self.zoom as usize,
+            algorithm: match self.trap {
+                Some(_) => PlottingAlgorithm::OrbitTrap,
+                None => PlottingAlgorithm::SmoothHistogram,
+            },
+            fractal: Some(self.fractal),
+            exponent: self.exponent,
+            deep_zoom: self.deep_zoom,
+            precision: Precision::Auto,
+            no_interior_checks: false,
+            subdivide: false,
+            thickness: 1.,
+            trap: self.trap.unwrap_or_default(),
+            trap_centre: self.trap_centre,
+            trap_size: self.trap_size,
+            stripe_density: 5.,
+            hist_equalisation: Equalisation::Count,
+            hist_exclude_interior: false,
+            hist_blend: 1.,
+            lighting: self.lighting,
+            light_angle: self.light_angle,
+            light_height: self.light_height,
+            samples,
+            sample_pattern: SamplePattern::Jittered,
+            adaptive: false,
+            edge_threshold: 32.,
+            raw_out: None,
+            no_image: false,
+            print_view: false,
+            command: match self.julia {
+                Some(c) => Commands::Julia {
+                    c_re: c.re,
+                    c_im: c.im,
+                    x: self.centre.re.clone(),
+                    y: self.centre.im.clone(),
+                    zoom: self.zoom,
+                },
+                None => Commands::Centre {
+                    x: self.centre.re.clone(),
+                    y: self.centre.im.clone(),
+                    zoom: self.zoom,
+                },
             },
             palette_repeats: self.palette_cycles,
-        };
-        let hue_array = args.get_hue_array()?;
-        let (width, height) = args.resolution.to_dimensions();
-        let palette = ColorPalette {
+        }
+    }
+
+    /// the palette as edited in the side panel
+    fn palette(&self) -> ColorPalette {
+        ColorPalette {
             color_vals: self
                 .palette_editor
                 .iter()
@@ -106,11 +294,7 @@ impl App {
                     value,
                 })
                 .collect(),
-        };
-        self.image = Some(ImageBuffer::from_fn(width as u32, height as u32, |x, y| {
-            palette.value(hue_array[x as usize][y as usize])
-        }));
-        Ok(())
+        }
     }
 
     fn render_opts(&mut self, ui: &mut Ui) {
@@ -120,13 +304,13 @@ impl App {
             ui.columns(2, |ui| {
                 ui[0].vertical_centered(|ui| {
                     if ui.button("      +      ").clicked() {
-                        self.zoom *= self.zoom_multiplier as f64;
+                        self.zoom = self.zoom.times(self.zoom_multiplier as f64);
                         refresh = true;
                     }
                 });
                 ui[1].vertical_centered(|ui| {
                     if ui.button("      -      ").clicked() {
-                        self.zoom /= self.zoom_multiplier as f64;
+                        self.zoom = self.zoom.times(1. / self.zoom_multiplier as f64);
                         refresh = true;
                     }
                 })
@@ -143,7 +327,66 @@ impl App {
                     .ctx
                     .input(|input| input.pointer.any_released()))
             {
-                self.refresh_image().unwrap();
+                self.refresh_image();
+            }
+            ui.label("exponent");
+            let exponent_field = ui.add(DragValue::new(&mut self.exponent).speed(0.05));
+
+            if (exponent_field.lost_focus()
+                && exponent_field
+                    .ctx
+                    .input(|input| input.key_pressed(Key::Enter)))
+                || (exponent_field
+                    .ctx
+                    .input(|input| input.pointer.any_released()))
+            {
+                self.refresh_image();
+            }
+            ui.label("image size");
+            let mut resized = false;
+            ui.horizontal(|ui| {
+                for side in [&mut self.width, &mut self.height] {
+                    let field = ui.add(DragValue::new(side).speed(4.).clamp_range(16..=7680));
+                    resized |= field.drag_released() || field.lost_focus();
+                }
+            });
+            if resized {
+                self.refresh_image();
+            }
+            ui.label("rotation (right-drag the image to turn it)");
+            let rotation_field = ui.add(
+                DragValue::new(&mut self.orientation.rotation)
+                    .speed(1.)
+                    .suffix("°"),
+            );
+            let mut turned = rotation_field.drag_released() || rotation_field.lost_focus();
+            ui.horizontal(|ui| {
+                turned |= ui
+                    .checkbox(&mut self.orientation.flip_horizontal, "flip horizontally")
+                    .changed();
+                turned |= ui
+                    .checkbox(&mut self.orientation.flip_vertical, "flip vertically")
+                    .changed();
+            });
+            if turned {
+                self.refresh_image();
+            }
+            if ui.checkbox(&mut self.deep_zoom, "deep zoom").changed() {
+                self.refresh_image();
+            }
+            // toggling swaps between the julia set for the current centre and the point
+            // the julia set was taken from
+            let mut julia = self.julia.is_some();
+            if ui.checkbox(&mut julia, "julia set").changed() {
+                match self.julia.take() {
+                    Some(c) => self.centre = c.into(),
+                    None => {
+                        self.julia = Some(self.centre.to_complex());
+                        self.centre = Point::default();
+                    }
+                }
+                self.zoom = Zoom::default();
+                self.refresh_image();
             }
             ui.label("palette cycles");
             let cycles_field = ui.add(DragValue::new(&mut self.palette_cycles).speed(1.));
@@ -154,7 +397,7 @@ impl App {
                     .input(|input| input.key_pressed(Key::Enter)))
                 || (cycles_field.ctx.input(|input| input.pointer.any_released()))
             {
-                self.refresh_image().unwrap();
+                self.refresh_image();
             }
 
             for (col, _) in self.palette_editor.iter_mut() {
@@ -162,13 +405,17 @@ impl App {
             }
 
             ui.add_space(20.);
-            ui.label(format!("centre: {}", self.centre.to_string()));
+            ui.label(format!("centre: {}", self.centre));
+            ui.label(format!("zoom: {}", self.zoom));
+            if let Some(c) = self.julia {
+                ui.label(format!("julia c: {}", c.to_string()));
+            }
 
             ui.add_space(20.);
             ui.label("sensitivity");
             ui.add(Slider::new(&mut self.zoom_multiplier, 1.1..=10.));
             if refresh {
-                self.refresh_image().unwrap();
+                self.refresh_image();
             }
             ui.add_space(20.);
             let mut conf = self.config.clone();
@@ -181,18 +428,149 @@ impl App {
                             .selectable_value(&mut self.palette, option.into(), option)
                             .clicked()
                         {
-                            self.refresh_image().unwrap();
+                            self.refresh_image();
                         };
                     }
                 });
 
+            ui.add_space(20.);
+            egui::ComboBox::from_label("fractal")
+                .selected_text(format!("{:?}", self.fractal))
+                .show_ui(ui, |ui| {
+                    for option in Fractal::ALL {
+                        if ui
+                            .selectable_value(&mut self.fractal, option, format!("{:?}", option))
+                            .clicked()
+                        {
+                            self.refresh_image();
+                        };
+                    }
+                });
+
+            ui.add_space(20.);
+            // möbius transforms need coefficients, which only the command line takes
+            egui::ComboBox::from_label("transform")
+                .selected_text(format!("{:?}", self.transform))
+                .show_ui(ui, |ui| {
+                    for option in TransformKind::ALL {
+                        if option != TransformKind::Mobius
+                            && ui
+                                .selectable_value(
+                                    &mut self.transform,
+                                    option,
+                                    format!("{:?}", option),
+                                )
+                                .clicked()
+                        {
+                            self.refresh_image();
+                        };
+                    }
+                });
+            if self.transform == TransformKind::Stereographic {
+                ui.label("globe tilt");
+                let field = ui.add(DragValue::new(&mut self.sphere_tilt).speed(1.).suffix("°"));
+                if field.drag_released() || field.lost_focus() {
+                    self.refresh_image();
+                }
+            }
+
+            ui.add_space(20.);
+            egui::ComboBox::from_label("orbit trap")
+                .selected_text(match self.trap {
+                    Some(shape) => format!("{:?}", shape),
+                    None => "none".into(),
+                })
+                .show_ui(ui, |ui| {
+                    if ui.selectable_value(&mut self.trap, None, "none").clicked() {
+                        self.refresh_image();
+                    }
+                    for option in TrapShape::ALL {
+                        if ui
+                            .selectable_value(&mut self.trap, Some(option), format!("{:?}", option))
+                            .clicked()
+                        {
+                            self.refresh_image();
+                        };
+                    }
+                });
+            if self.trap.is_some() {
+                ui.label("trap centre");
+                // only re-render once a value's been settled on, not on every step of a drag
+                let mut changed = false;
+                ui.horizontal(|ui| {
+                    for part in [&mut self.trap_centre.re, &mut self.trap_centre.im] {
+                        let field = ui.add(DragValue::new(part).speed(0.01));
+                        changed |= field.drag_released() || field.lost_focus();
+                    }
+                });
+                ui.label("trap size");
+                let field = ui.add(
+                    DragValue::new(&mut self.trap_size)
+                        .speed(0.01)
+                        .clamp_range(0.001..=10.),
+                );
+                changed |= field.drag_released() || field.lost_focus();
+                if changed {
+                    self.refresh_image();
+                }
+            }
+
+            ui.add_space(20.);
+            egui::ComboBox::from_label("lighting")
+                .selected_text(match self.lighting {
+                    Some(model) => format!("{:?}", model),
+                    None => "none".into(),
+                })
+                .show_ui(ui, |ui| {
+                    if ui
+                        .selectable_value(&mut self.lighting, None, "none")
+                        .clicked()
+                    {
+                        self.refresh_image();
+                    }
+                    for option in [LightingModel::Lambert, LightingModel::BlinnPhong] {
+                        if ui
+                            .selectable_value(
+                                &mut self.lighting,
+                                Some(option),
+                                format!("{:?}", option),
+                            )
+                            .clicked()
+                        {
+                            self.refresh_image();
+                        };
+                    }
+                });
+            if self.lighting.is_some() {
+                ui.label("light angle");
+                let angle_field = ui.add(
+                    DragValue::new(&mut self.light_angle)
+                        .speed(1.)
+                        .clamp_range(-180.0..=180.),
+                );
+                ui.label("light height");
+                let height_field = ui.add(
+                    DragValue::new(&mut self.light_height)
+                        .speed(0.01)
+                        .clamp_range(0.0..=10.),
+                );
+                if [angle_field, height_field]
+                    .iter()
+                    .any(|field| field.drag_released() || field.lost_focus())
+                {
+                    self.refresh_image();
+                }
+            }
+
             ui.add_space(20.);
             ui.text_edit_singleline(&mut self.new_landmark_name);
             ui.add_space(10.);
             if ui.button("save new landmark").clicked() {
                 let landmark = NamedPoint {
-                    point: self.centre,
-                    zoom: self.zoom as _,
+                    point: self.centre.clone(),
+                    zoom: self.zoom,
+                    fractal: self.fractal,
+                    rotation: self.orientation.rotation,
                 };
                 conf.named_points
                     .insert(self.new_landmark_name.clone(), landmark);
@@ -215,46 +593,117 @@ impl App {
                             .clicked()
                         {
                             let point = self.config.named_points.get(point_name).unwrap();
-                            self.centre = point.point;
-                            self.zoom = point.zoom as f64;
-                            self.refresh_image().unwrap();
+                            self.centre = point.point.clone();
+                            self.zoom = point.zoom;
+                            self.orientation.rotation = point.rotation;
+                            self.fractal = point.fractal;
+                            self.julia = None;
+                            self.refresh_image();
                         }
                     }
                 });
             ui.add_space(20.);
+            ui.label("export anti-aliasing (samples per side)");
+            ui.add(
+                DragValue::new(&mut self.export_sample_side)
+                    .speed(0.05)
+                    .clamp_range(1..=8),
+            );
             if ui.button("save image").clicked() {
-                self.refresh_image().unwrap();
-                self.image.take().unwrap().save("mandelbrot.png").unwrap();
+                let saved = self
+                    .render_args(self.export_sample_side.pow(2))
+                    .get_image_with_palette(&self.palette(), &Renderer::new())
+                    .and_then(|(image, _)| Ok(image.save("mandelbrot.png")?));
+                if let Err(error) = saved {
+                    self.error = Some(error.to_string());
+                }
             };
         });
     }
 
     fn render_image(&mut self, ui: &mut Ui) {
         // TODO: resize image with image crate according to window size
-        if self.image_texture.is_none() {
-            self.refresh_image().unwrap();
+        if self.image_texture.is_none() && self.job.is_none() && self.error.is_none() {
+            self.refresh_image();
+        }
+        // tiles of a running render are shown as they finish, until the render replaces them
+        if let Some(job) = &self.job {
+            let mut preview = job.preview.lock().unwrap();
+            if preview.changed {
+                let size = [
+                    preview.image.width() as usize,
+                    preview.image.height() as usize,
+                ];
+                self.image_texture = Some(ui.ctx().load_texture(
+                    "image",
+                    ColorImage::from_rgb(size, &preview.image),
+                    Default::default(),
+                ));
+                preview.changed = false;
+            }
         }
         if let Some(image) = self.image.take() {
+            let size = [image.width() as usize, image.height() as usize];
             self.image_texture = Some(ui.ctx().load_texture(
                 "image",
-                ColorImage::from_rgb([960, 540], &image),
+                ColorImage::from_rgb(size, &image),
                 Default::default(),
             ));
         }
 
-        let texture = self.image_texture.clone().unwrap();
+        if let Some(error) = &self.error {
+            ui.colored_label(Color32::RED, error);
+        }
+        if let Some(job) = &self.job {
+            let fraction = *job.progress.lock().unwrap();
+            let mut cancel = false;
+            ui.horizontal(|ui| {
+                cancel = ui.button("cancel").clicked();
+                ui.add(ProgressBar::new(fraction as f32).show_percentage());
+            });
+            if cancel {
+                if let Some(job) = self.job.take() {
+                    job.cancel.cancel();
+                }
+            }
+        }
+
+        let Some(texture) = self.image_texture.clone() else {
+            return;
+        };
         let image_response =
-            ui.add(Image::new(&texture, texture.size_vec2()).sense(Sense::click()));
+            ui.add(Image::new(&texture, texture.size_vec2()).sense(Sense::click_and_drag()));
+        let rect = image_response.rect;
 
         if image_response.clicked() {
-            let rect = image_response.rect;
             let rel_position = image_response.hover_pos().unwrap() - rect.left_top();
-            let (x_bounds, y_bounds) = get_intervals(self.centre, self.zoom);
-            self.centre = Complex::new(
-                x_bounds.lerp(rel_position.x as f64 / rect.width() as f64),
-                y_bounds.lerp(rel_position.y as f64 / rect.height() as f64),
+            // the texture is the size of the last finished render, even if the size's since
+            // been changed
+            let [width, height] = texture.size();
+            let pixel_map = PixelMap::new(self.zoom.scale(), self.orientation, width, height);
+            // the centre keeps the digits deep zooms need, so it's moved rather than added to
+            self.centre = self.centre.offset(
+                pixel_map.offset(
+                    rel_position.x as f64 / rect.width() as f64 * width as f64,
+                    rel_position.y as f64 / rect.height() as f64 * height as f64,
+                ),
+                pixel_map.spacing(),
             );
-            self.refresh_image().unwrap();
+            self.refresh_image();
+        }
+
+        // turn the view by however far the pointer swept around the middle of the image, so
+        // what's under it turns along with it
+        if image_response.dragged_by(PointerButton::Secondary) {
+            if let Some(position) = image_response.interact_pointer_pos() {
+                let angle = |v: Vec2| (-v.y).atan2(v.x) as f64;
+                let now = position - rect.center();
+                let before = now - image_response.drag_delta();
+                self.orientation.rotation -= (angle(now) - angle(before)).to_degrees();
+            }
+        }
+        if image_response.drag_released() {
+            self.refresh_image();
         }
     }
 }